@@ -0,0 +1,41 @@
+//! Compare the throughput of a bump only pool against the general free list
+//! path, on a workload that frees every entry before a bulk reset. Run with
+//! `cargo +nightly bench`.
+#![feature(allocator_api)]
+#![feature(slice_ptr_get)]
+#![feature(test)]
+
+extern crate test;
+
+use std::alloc::{Allocator, Layout};
+use std::ptr::NonNull;
+
+use memory_pool::MemoryPool;
+use test::Bencher;
+
+const CAPACITY: usize = 2_usize.pow(16);
+
+/// Allocate every block of the pool and free them all again, then reset it.
+fn churn(pool: &mut MemoryPool, blocks: &mut Vec<NonNull<u8>>, layout: Layout) {
+    blocks.extend((0..CAPACITY).map(|_| pool.allocate(layout).unwrap().as_non_null_ptr()));
+    for block in blocks.drain(..) {
+        unsafe { pool.deallocate(test::black_box(block), layout) };
+    }
+    pool.reset();
+}
+
+#[bench]
+fn free_list(b: &mut Bencher) {
+    let layout = Layout::new::<usize>();
+    let mut pool = MemoryPool::new(CAPACITY, layout);
+    let mut blocks = Vec::with_capacity(CAPACITY);
+    b.iter(|| churn(&mut pool, &mut blocks, layout));
+}
+
+#[bench]
+fn bump_only(b: &mut Bencher) {
+    let layout = Layout::new::<usize>();
+    let mut pool = MemoryPool::builder(CAPACITY, layout).bump_only(true).build();
+    let mut blocks = Vec::with_capacity(CAPACITY);
+    b.iter(|| churn(&mut pool, &mut blocks, layout));
+}
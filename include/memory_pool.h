@@ -0,0 +1,39 @@
+/* C interface to the memory-pool crate, enabled by its `capi` feature.
+ *
+ * Link against the crate built as a static or dynamic library, for example
+ * with `cargo rustc --release --features capi --crate-type staticlib`. All
+ * functions accept a null pool and then do nothing; failures are reported
+ * as null.
+ */
+#ifndef MEMORY_POOL_H
+#define MEMORY_POOL_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* An opaque fixed-size block allocator. */
+typedef struct MemoryPool MemoryPool;
+
+/* Create a pool of `capacity` blocks of the given size and alignment. Returns
+ * null on an invalid layout, a too large capacity or out of memory. */
+MemoryPool *mp_new(size_t capacity, size_t size, size_t align);
+
+/* Allocate a block, or return null when the pool is out of blocks. */
+uint8_t *mp_alloc(MemoryPool *pool);
+
+/* Return a block to the pool. Pointers that are not the start of one of its
+ * blocks are ignored. */
+void mp_free(MemoryPool *pool, uint8_t *block);
+
+/* Destroy the pool along with every block still allocated from it. */
+void mp_destroy(MemoryPool *pool);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* MEMORY_POOL_H */
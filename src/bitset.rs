@@ -0,0 +1,40 @@
+use alloc::vec;
+use alloc::vec::Vec;
+
+/// A fixed-size set of indices, stored one bit per index.
+#[derive(Clone)]
+pub(crate) struct Bitset {
+    words: Vec<usize>,
+    len: usize,
+}
+
+impl Bitset {
+    const BITS: usize = usize::BITS as usize;
+
+    /// Create an empty set for the indices `0..len`.
+    pub(crate) fn new(len: usize) -> Self {
+        Self { words: vec![0; len.div_ceil(Self::BITS)], len }
+    }
+
+    /// Add the index to the set.
+    pub(crate) fn insert(&mut self, index: usize) {
+        debug_assert!(index < self.len);
+        self.words[index / Self::BITS] |= 1 << (index % Self::BITS);
+    }
+
+    /// Remove the index from the set.
+    pub(crate) fn remove(&mut self, index: usize) {
+        debug_assert!(index < self.len);
+        self.words[index / Self::BITS] &= !(1 << (index % Self::BITS));
+    }
+
+    /// Add all indices in `start..self.len()` to the set.
+    pub(crate) fn insert_from(&mut self, start: usize) {
+        (start..self.len).for_each(|index| self.insert(index));
+    }
+
+    /// Check if the index is in the set.
+    pub(crate) fn contains(&self, index: usize) -> bool {
+        index < self.len && self.words[index / Self::BITS] & (1 << (index % Self::BITS)) != 0
+    }
+}
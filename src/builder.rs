@@ -0,0 +1,167 @@
+use alloc::alloc::{AllocError, Layout};
+
+use crate::{block_layout, MemoryPool, Mode, Quota, PAGE_SIZE};
+
+/// Configures a [`MemoryPool`] beyond its capacity and layout. Create one with
+/// [`MemoryPool::builder`].
+#[derive(Clone, Debug)]
+pub struct PoolBuilder {
+    capacity: usize,
+    layout: Layout,
+    bump_only: bool,
+    stack: bool,
+    adaptive: bool,
+    name: Option<&'static str>,
+    quota: Option<Quota>,
+    page_aligned: bool,
+    reject_zst: bool,
+}
+
+impl PoolBuilder {
+    /// Start configuring a pool with the given capacity and layout. All other
+    /// options start at the behaviour of [`MemoryPool::new`].
+    pub fn new(capacity: usize, layout: Layout) -> Self {
+        Self {
+            capacity,
+            layout,
+            bump_only: false,
+            stack: false,
+            adaptive: false,
+            name: None,
+            quota: None,
+            page_aligned: false,
+            reject_zst: false,
+        }
+    }
+
+    /// Never reuse individually freed blocks. Allocation only advances to the
+    /// adjacent block and deallocation does nothing, so memory is reclaimed
+    /// by [`MemoryPool::reset`] alone. This skips all free list bookkeeping
+    /// for arenas whose entries are only ever freed in bulk.
+    ///
+    /// Freed blocks keep counting towards [`MemoryPool::len`] until the reset.
+    /// This cannot be combined with [stack](Self::stack) or
+    /// [adaptive](Self::adaptive) mode.
+    pub fn bump_only(mut self, bump_only: bool) -> Self {
+        self.bump_only = bump_only;
+        self
+    }
+
+    /// Only allow freeing the most recently allocated block, like popping a
+    /// stack. Allocation advances to the adjacent block and deallocation
+    /// steps back, so there is no free list and blocks stay in address order.
+    ///
+    /// Deallocating any other block panics. This cannot be combined with
+    /// [bump only](Self::bump_only) or [adaptive](Self::adaptive) mode.
+    pub fn stack(mut self, stack: bool) -> Self {
+        self.stack = stack;
+        self
+    }
+
+    /// Allocate like [bump only](Self::bump_only) mode until the first block
+    /// is freed, then switch to a free list for good. A bulk allocation phase
+    /// skips the free list bookkeeping, while later interleaved frees are
+    /// still reused. A [reset](MemoryPool::reset) does not switch back.
+    ///
+    /// This cannot be combined with [bump only](Self::bump_only) or
+    /// [stack](Self::stack) mode.
+    pub fn adaptive(mut self, adaptive: bool) -> Self {
+        self.adaptive = adaptive;
+        self
+    }
+
+    /// Name the pool, so it can be told apart in its `Debug` and `Display`
+    /// output. Pools are unnamed by default.
+    pub fn name(mut self, name: &'static str) -> Self {
+        self.name = Some(name);
+        self
+    }
+
+    /// Count every allocation against a quota shared with other pools.
+    /// Allocation fails once the quota is used up, even if this pool still
+    /// has free blocks.
+    pub fn quota(mut self, quota: Quota) -> Self {
+        self.quota = Some(quota);
+        self
+    }
+
+    /// Align the memory region of the pool to a 4 KiB page boundary, so the
+    /// first block starts at the beginning of a page.
+    ///
+    /// This only raises the alignment of the region, not of the blocks. The
+    /// global allocator may still need up to a page of extra space to satisfy
+    /// it, and a region smaller than a page still takes up part of one.
+    pub fn page_aligned(mut self, page_aligned: bool) -> Self {
+        self.page_aligned = page_aligned;
+        self
+    }
+
+    /// Refuse to create a pool for a zero sized layout. Every block still
+    /// holds a free list link, so such a pool spends a pointer of memory on
+    /// each allocation of nothing. By default zero sized layouts are allowed.
+    pub fn reject_zst(mut self, reject_zst: bool) -> Self {
+        self.reject_zst = reject_zst;
+        self
+    }
+
+    /// Create the configured memory pool.
+    ///
+    /// # Panics
+    ///
+    /// This will panic on incorrect layouts, on zero sized layouts if they are
+    /// [rejected](Self::reject_zst), if more than one of bump only, stack and
+    /// adaptive mode is enabled and if the global allocator is out of memory.
+    #[track_caller]
+    pub fn build(self) -> MemoryPool {
+        assert!(!self.rejects_layout(), "zero sized layouts are rejected by this pool");
+        let mode = self.mode().expect("bump only, stack and adaptive mode cannot be combined");
+        let block = block_layout(self.layout);
+        let pool = MemoryPool::with_block_layout(self.capacity, self.layout, block, self.array_align());
+        self.configure(pool, mode)
+    }
+
+    /// Create the configured memory pool like [`PoolBuilder::build`], but
+    /// return an error instead of panicking when the layout is
+    /// [rejected](Self::reject_zst), several modes are enabled at once, the
+    /// capacity is too large for the layout or the global allocator is out of
+    /// memory.
+    ///
+    /// # Panics
+    ///
+    /// This will still panic on incorrect layouts.
+    #[track_caller]
+    pub fn try_build(self) -> Result<MemoryPool, AllocError> {
+        if self.rejects_layout() { return Err(AllocError) }
+        let mode = self.mode().ok_or(AllocError)?;
+        let block = block_layout(self.layout);
+        let pool = MemoryPool::try_with_block_layout(self.capacity, self.layout, block, self.array_align())?;
+        Ok(self.configure(pool, mode))
+    }
+
+    fn rejects_layout(&self) -> bool {
+        self.reject_zst && self.layout.size() == 0
+    }
+
+    /// The mode that was enabled, or `None` if several were.
+    fn mode(&self) -> Option<Mode> {
+        match (self.bump_only, self.stack, self.adaptive) {
+            (false, false, false) => Some(Mode::FreeList),
+            (true, false, false) => Some(Mode::BumpOnly),
+            (false, true, false) => Some(Mode::Stack),
+            (false, false, true) => Some(Mode::Adaptive),
+            _ => None,
+        }
+    }
+
+    fn array_align(&self) -> usize {
+        if self.page_aligned { PAGE_SIZE } else { 1 }
+    }
+
+    /// Apply the options that do not affect the memory region.
+    fn configure(self, mut pool: MemoryPool, mode: Mode) -> MemoryPool {
+        pool.mode.set(mode);
+        pool.name = self.name;
+        pool.quota = self.quota;
+        pool
+    }
+}
@@ -0,0 +1,76 @@
+//! A C interface to [`MemoryPool`], see `include/memory_pool.h`.
+//!
+//! Pools are handed to C as opaque pointers. Every function accepts a null
+//! pool and then does nothing, and allocation failures are reported as null.
+
+use core::ptr::{self, NonNull};
+
+use alloc::alloc::{Allocator, Layout};
+use alloc::boxed::Box;
+
+use crate::{try_block_layout, MemoryPool, PtrStatus};
+
+/// Create a pool of `capacity` blocks of the given size and alignment.
+///
+/// Returns null if the alignment is not a power of two, the layout or the
+/// capacity is too large, or the global allocator is out of memory.
+#[no_mangle]
+pub extern "C" fn mp_new(capacity: usize, size: usize, align: usize) -> *mut MemoryPool {
+    // Panicking would abort the host, so every step is checked.
+    let Ok(layout) = Layout::from_size_align(size, align) else { return ptr::null_mut() };
+    let Some(block) = try_block_layout(layout) else { return ptr::null_mut() };
+    match MemoryPool::try_with_block_layout(capacity, layout, block, 1) {
+        Ok(pool) => Box::into_raw(Box::new(pool)),
+        Err(_) => ptr::null_mut(),
+    }
+}
+
+/// Allocate a block from the pool. Returns null if the pool is null or out
+/// of blocks.
+///
+/// # Safety
+///
+/// The pool must be null or created by [`mp_new`] and not yet destroyed.
+#[no_mangle]
+pub unsafe extern "C" fn mp_alloc(pool: *mut MemoryPool) -> *mut u8 {
+    let Some(pool) = pool.as_ref() else { return ptr::null_mut() };
+    match pool.allocate(pool.requested) {
+        Ok(block) => block.as_mut_ptr(),
+        Err(_) => ptr::null_mut(),
+    }
+}
+
+/// Return a block to the pool. Null blocks, and pointers that are not the
+/// start of a block of this pool, are ignored.
+///
+/// # Safety
+///
+/// The pool must be null or created by [`mp_new`] and not yet destroyed. A
+/// block of the pool must be currently allocated, and is invalid afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn mp_free(pool: *mut MemoryPool, block: *mut u8) {
+    let Some(pool) = pool.as_ref() else { return };
+    let Some(block) = NonNull::new(block) else { return };
+    if pool.validate_ptr(block) == PtrStatus::Owned {
+        pool.deallocate(block, pool.requested);
+    }
+}
+
+/// Destroy the pool, freeing its memory along with every block still
+/// allocated from it.
+///
+/// # Safety
+///
+/// The pool must be null or created by [`mp_new`] and not yet destroyed. It
+/// is invalid afterwards, as are all of its blocks.
+#[no_mangle]
+pub unsafe extern "C" fn mp_destroy(pool: *mut MemoryPool) {
+    if !pool.is_null() {
+        let pool = Box::from_raw(pool);
+        // Outstanding blocks are freed on purpose here, not leaked. These
+        // pools have no quota, so nothing else depends on the count.
+        #[cfg(feature = "leak-check")]
+        pool.len.set(0);
+        drop(pool);
+    }
+}
@@ -0,0 +1,55 @@
+use alloc::alloc::Layout;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// The configuration of a [`MemoryPool`](crate::MemoryPool): its capacity and
+/// the layout of the elements it was made for. With the `serde` feature this
+/// can be stored and read back, to reconstruct pools on startup.
+///
+/// This only describes the pool, not its contents.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "RawPoolConfig"))]
+pub struct PoolConfig {
+    /// The maximum number of entries in the pool.
+    pub capacity: usize,
+    /// The size of the elements in bytes.
+    pub size: usize,
+    /// The alignment of the elements in bytes. This must be a power of two.
+    pub align: usize,
+}
+
+impl PoolConfig {
+    /// Create the configuration for a pool with the given capacity and layout.
+    pub fn new(capacity: usize, layout: Layout) -> Self {
+        Self { capacity, size: layout.size(), align: layout.align() }
+    }
+
+    /// The layout of the elements, if the size and alignment are valid.
+    pub fn layout(&self) -> Option<Layout> {
+        Layout::from_size_align(self.size, self.align).ok()
+    }
+}
+
+/// A configuration as it was read, before the layout was validated.
+#[cfg(feature = "serde")]
+#[derive(Deserialize)]
+struct RawPoolConfig {
+    capacity: usize,
+    size: usize,
+    align: usize,
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<RawPoolConfig> for PoolConfig {
+    type Error = &'static str;
+
+    fn try_from(raw: RawPoolConfig) -> Result<Self, Self::Error> {
+        let config = PoolConfig { capacity: raw.capacity, size: raw.size, align: raw.align };
+        match config.layout() {
+            Some(_) => Ok(config),
+            None => Err("align must be a power of two and the size must not overflow"),
+        }
+    }
+}
@@ -0,0 +1,87 @@
+use core::ptr::NonNull;
+
+use alloc::alloc::{Allocator, AllocError, Layout};
+
+use crate::{MemoryPool, PoolIntrospect, PoolMetrics};
+
+/// A [`MemoryPool`] that can be shared between a main loop and interrupt
+/// handlers. Every operation runs inside a critical section from the
+/// `critical-section` crate, which on a single core typically disables
+/// interrupts, so no atomics are needed.
+///
+/// The pool cannot share a [`Quota`](crate::Quota) with other pools, as those
+/// would touch it outside of a critical section.
+pub struct CriticalSectionPool {
+    pool: MemoryPool,
+}
+
+// All access to the pool happens inside a critical section.
+unsafe impl Sync for CriticalSectionPool {}
+
+impl CriticalSectionPool {
+    /// Wrap a pool, so it can be shared between interrupt contexts.
+    ///
+    /// # Panics
+    ///
+    /// This will panic if the pool counts against a quota.
+    #[track_caller]
+    pub fn new(pool: MemoryPool) -> Self {
+        assert!(pool.quota.is_none(), "pools with a quota cannot be shared between interrupt contexts");
+        Self { pool }
+    }
+
+    /// Run `f` on the pool, inside a critical section.
+    pub fn with<R>(&self, f: impl FnOnce(&MemoryPool) -> R) -> R {
+        critical_section::with(|_| f(&self.pool))
+    }
+
+    /// Unwrap the pool again.
+    pub fn into_inner(self) -> MemoryPool {
+        self.pool
+    }
+}
+
+unsafe impl Allocator for CriticalSectionPool {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        self.with(|pool| pool.allocate(layout))
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        self.with(|pool| pool.deallocate(ptr, layout))
+    }
+
+    unsafe fn grow(&self, ptr: NonNull<u8>, old_layout: Layout, new_layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        self.with(|pool| pool.grow(ptr, old_layout, new_layout))
+    }
+
+    unsafe fn grow_zeroed(&self, ptr: NonNull<u8>, old_layout: Layout, new_layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        self.with(|pool| pool.grow_zeroed(ptr, old_layout, new_layout))
+    }
+
+    unsafe fn shrink(&self, ptr: NonNull<u8>, old_layout: Layout, new_layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        self.with(|pool| pool.shrink(ptr, old_layout, new_layout))
+    }
+}
+
+impl PoolIntrospect for CriticalSectionPool {
+    fn capacity(&self) -> usize {
+        self.with(MemoryPool::capacity)
+    }
+
+    fn len(&self) -> usize {
+        self.with(MemoryPool::len)
+    }
+
+    fn block_size(&self) -> usize {
+        self.with(MemoryPool::block_size)
+    }
+
+    fn available(&self) -> usize {
+        self.with(MemoryPool::available)
+    }
+
+    // Take the whole snapshot in one critical section, so it is consistent.
+    fn metrics(&self) -> PoolMetrics {
+        self.with(MemoryPool::metrics)
+    }
+}
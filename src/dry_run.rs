@@ -0,0 +1,107 @@
+use core::ptr::NonNull;
+use core::cell::Cell;
+
+use alloc::alloc::{Allocator, AllocError, Layout};
+
+use crate::{block_layout, union_layout};
+
+/// A stand-in for [`MemoryPool`](crate::MemoryPool) that never reserves any
+/// memory, but records how many blocks were in use at once. Replaying a
+/// workload against it tells you the capacity a real pool needs.
+///
+/// Allocations follow the same layout and exhaustion rules as a real pool,
+/// but the returned pointers are dangling. They must never be read or
+/// written, so only drive this through [`Allocator::allocate`] and
+/// [`Allocator::deallocate`] directly, not through collections.
+pub struct DryRunPool {
+    /// The layout requirement of the blocks a real pool would have.
+    layout: Layout,
+    /// The maximum number of blocks that may be allocated at once.
+    capacity: usize,
+    /// The number of blocks that are currently allocated.
+    len: Cell<usize>,
+    /// The highest number of blocks that were allocated at once.
+    peak: Cell<usize>,
+    /// The total number of successful allocations.
+    total: Cell<usize>,
+}
+
+impl DryRunPool {
+    /// Create a dry run pool that behaves like a [`MemoryPool`] with the given
+    /// capacity and layout.
+    ///
+    /// [`MemoryPool`]: crate::MemoryPool
+    ///
+    /// # Panics
+    ///
+    /// This will panic on incorrect layouts.
+    #[track_caller]
+    pub fn new(capacity: usize, layout: Layout) -> Self {
+        Self {
+            layout: block_layout(layout),
+            capacity,
+            len: Cell::new(0),
+            peak: Cell::new(0),
+            total: Cell::new(0),
+        }
+    }
+
+    /// The maximum number of entries this pool can contain.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// The number of blocks that are currently allocated.
+    pub fn len(&self) -> usize {
+        self.len.get()
+    }
+
+    /// Check if no blocks are currently allocated.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The size of every block a real pool would have, in bytes.
+    pub fn block_size(&self) -> usize {
+        self.layout.size()
+    }
+
+    /// The highest number of blocks that were allocated at once. This is the
+    /// minimum capacity a real pool needs for the same workload.
+    pub fn peak(&self) -> usize {
+        self.peak.get()
+    }
+
+    /// The total number of successful allocations.
+    pub fn total(&self) -> usize {
+        self.total.get()
+    }
+}
+
+unsafe impl Allocator for DryRunPool {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        // Check if given layout fits the layout requirements.
+        if self.layout != union_layout(self.layout, layout) { return Err(AllocError) }
+
+        // Check if we have run out of memory
+        let len = self.len.get();
+        if len == self.capacity { return Err(AllocError) }
+
+        self.len.set(len + 1);
+        self.peak.set(core::cmp::max(self.peak.get(), len + 1));
+        self.total.set(self.total.get() + 1);
+
+        // A dangling pointer that still satisfies the alignment.
+        let block = NonNull::new(core::ptr::without_provenance_mut(self.layout.align()))
+            .ok_or(AllocError)?;
+        Ok(NonNull::slice_from_raw_parts(block, self.layout.size()))
+    }
+
+    unsafe fn deallocate(&self, _ptr: NonNull<u8>, layout: Layout) {
+        // Check if given layout fits the layout requirements.
+        debug_assert_eq!(self.layout, union_layout(self.layout, layout));
+        debug_assert!(self.len.get() > 0);
+
+        self.len.set(self.len.get() - 1);
+    }
+}
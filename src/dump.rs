@@ -0,0 +1,33 @@
+use alloc::vec::Vec;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// A snapshot of the free list of a [`MemoryPool`](crate::MemoryPool), taken
+/// by [`MemoryPool::dump_structure`](crate::MemoryPool::dump_structure) for
+/// post-mortem debugging. With the `serde` feature it can be written out.
+///
+/// Addresses are plain numbers, so the dump stays meaningful after the pool
+/// is gone. The walk never trusts a link, so a corrupted free list is
+/// captured up to the point where it goes wrong.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct PoolDump {
+    /// The address of the first block of the region.
+    pub base: usize,
+    /// The size of every block in bytes.
+    pub block_size: usize,
+    /// The address the free list starts at.
+    pub head: usize,
+    /// The addresses of the freed blocks on the free list, in list order.
+    pub free_nodes: Vec<usize>,
+    /// The address where the untouched tail starts, if the list reaches it.
+    pub tail: Option<usize>,
+    /// The number of blocks that are allocated.
+    pub len: usize,
+    /// Whether the free list runs back into a block it already visited.
+    pub cyclic: bool,
+    /// A link that points outside the region or into the middle of a block,
+    /// where the walk stopped.
+    pub invalid_link: Option<usize>,
+}
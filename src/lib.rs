@@ -33,33 +33,103 @@
 //! ```
 #![feature(allocator_api)]
 #![feature(slice_ptr_get)]
-#![feature(alloc_layout_extra)]
 
 #![cfg_attr(not(any(feature = "std", test)), no_std)]
 #![deny(missing_docs)]
 
-#[cfg(not(feature = "std"))]
 extern crate alloc;
 
 #[cfg(test)]
 mod test;
 
+mod bitset;
+mod builder;
+#[cfg(feature = "capi")]
+pub mod capi;
+mod config;
+#[cfg(feature = "critical-section")]
+mod critical;
+mod dry_run;
+mod dump;
+mod metrics;
+mod quota;
+mod tiered;
+
+pub use builder::PoolBuilder;
+pub use config::PoolConfig;
+#[cfg(feature = "critical-section")]
+pub use critical::CriticalSectionPool;
+pub use dry_run::DryRunPool;
+pub use dump::PoolDump;
+pub use metrics::{PoolIntrospect, PoolMetrics};
+pub use quota::Quota;
+pub use tiered::TieredPool;
+
 use core::ptr::NonNull;
-use core::cell::Cell;
+use core::cell::{Cell, RefCell};
+use core::fmt;
+
+use bitset::Bitset;
+use metrics::{LowWater, Watchdog};
 
 use alloc::alloc::{Allocator, AllocError, Global, Layout};
+use alloc::boxed::Box;
+use alloc::vec::Vec;
 
 /// A memory pool for (de)allocation fixed-size blocks in constant time. It is
 /// not thread safe and incurs space overhead for types smaller than a pointer.
 pub struct MemoryPool {
     /// The layout requirement of the blocks in our allocator.
     layout: Layout,
+    /// The layout the pool was constructed for, before it was grown to fit a
+    /// free list link.
+    requested: Layout,
     /// The memory region from which we will allocate.
     memory: NonNull<[u8]>,
+    /// Where the memory region came from, so we can free it.
+    backing: Backing,
     /// Pointer to the next free item. We store this as a u8 pointer because
     /// the free list nodes are stored based on the layout of the blocks, not
     /// their own.
     next: Cell<NonNull<u8>>,
+    /// The number of blocks that are currently allocated.
+    len: Cell<usize>,
+    /// One past the highest block handed out since the last reset. Links from
+    /// here on were never written, so a reset can leave them alone.
+    touched: Cell<usize>,
+    /// How freed blocks are reused. Only an adaptive pool changes its mode.
+    mode: Cell<Mode>,
+    /// A name to tell pools apart in diagnostics.
+    name: Option<&'static str>,
+    /// A predicate that can reject layouts which would otherwise fit.
+    filter: Option<fn(Layout) -> bool>,
+    /// Whether corruption was detected, after which the pool refuses to be
+    /// used.
+    poisoned: Cell<bool>,
+    /// The number of free blocks that were dropped from the free list, and
+    /// cannot be allocated until a reset.
+    forgotten: usize,
+    /// The indices of the forgotten blocks, if any were forgotten.
+    forgotten_set: Option<Bitset>,
+    /// The indices of never allocated blocks whose free list link was made
+    /// explicit while claiming a block past them, if there are any. They are
+    /// linked like freed blocks, but must not be forgotten like them.
+    relinked: RefCell<Option<Bitset>>,
+    /// A budget of blocks shared with other pools.
+    quota: Option<Quota>,
+    /// A callback for when the pool is about to run out of blocks.
+    low_water: Option<LowWater>,
+    /// A callback for when many blocks are allocated without any frees.
+    watchdog: Option<Watchdog>,
+    /// The allocation ID of every block's occupant, or zero if it is free.
+    #[cfg(feature = "alloc-id")]
+    ids: Box<[Cell<u64>]>,
+    /// The most recently handed out allocation ID.
+    #[cfg(feature = "alloc-id")]
+    last_id: Cell<u64>,
+    /// The exact layout every block was allocated with, if it is allocated.
+    #[cfg(feature = "strict-layout")]
+    layouts: Box<[Cell<Option<Layout>>]>,
 }
 
 impl MemoryPool {
@@ -73,35 +143,1233 @@ impl MemoryPool {
     ///
     /// This will panic on incorrect layouts and if the global allocator is out
     /// of memory.
+    #[track_caller]
     pub fn new(capacity: usize, layout: Layout) -> Self {
-        let layout = union_layout(layout, Layout::new::<Free>())
-            // Pad the layout to be multiples of the alignment. We use this
-            // property when calculating the next free entry.
-            .pad_to_align();
+        Self::with_block_layout(capacity, layout, block_layout(layout), 1)
+    }
+
+    /// Create a memory pool like [`MemoryPool::new`], but return an error
+    /// instead of panicking when the capacity is too large for the layout or
+    /// the global allocator is out of memory.
+    ///
+    /// # Panics
+    ///
+    /// This will still panic on incorrect layouts.
+    #[track_caller]
+    pub fn try_new(capacity: usize, layout: Layout) -> Result<Self, AllocError> {
+        Self::try_with_block_layout(capacity, layout, block_layout(layout), 1)
+    }
+
+    /// Create a memory pool sized from the peak number of blocks in use on a
+    /// previous run, plus `headroom` as a fraction of it. With a peak of 100
+    /// and a headroom of 0.5 the capacity is 150, rounded up when the product
+    /// is not whole. Without a previous peak the capacity is 64 blocks.
+    ///
+    /// Storing the peak between runs is up to the caller, for example taken
+    /// from [`DryRunPool::peak`] or the highest [`MemoryPool::len`] seen.
+    /// Negative headroom counts as none. An `f32` cannot hold fractions like
+    /// 0.2 exactly, so the headroom is rounded to the nearest millionth first:
+    /// a headroom of 4e-7 on a peak of 10,000,000 adds nothing.
+    ///
+    /// # Panics
+    ///
+    /// This will panic on incorrect layouts, if the scaled capacity does not
+    /// fit in a `usize` (for example with an infinite headroom), and if the
+    /// global allocator is out of memory.
+    #[track_caller]
+    pub fn auto_tuned(layout: Layout, last_peak: Option<usize>, headroom: f32) -> Self {
+        let capacity = match last_peak {
+            Some(peak) => {
+                // A headroom like 0.2 is not exact in binary, so snap it to
+                // millionths. Scale in f64 so peaks above 2^24 stay exact.
+                let headroom = headroom.max(0.0) as f64;
+                let headroom = if headroom < 1e12 {
+                    (headroom * 1e6 + 0.5) as u64 as f64 / 1e6
+                } else {
+                    headroom
+                };
+                let scaled = peak as f64 * (1.0 + headroom);
+                assert!(scaled < usize::MAX as f64, "auto tuned capacity overflow");
+                // The cast rounds down, so round back up unless the fraction
+                // is rounding error.
+                let capacity = scaled as usize;
+                let fraction = scaled - capacity as f64;
+                let capacity = if fraction > scaled * f64::EPSILON { capacity + 1 } else { capacity };
+                capacity.max(peak)
+            }
+            None => DEFAULT_CAPACITY,
+        };
+        Self::new(capacity, layout)
+    }
+
+    /// The largest capacity a pool for the given layout can have on this
+    /// target, before the size of its region overflows. Larger capacities make
+    /// [`MemoryPool::new`] panic and [`MemoryPool::try_new`] fail.
+    ///
+    /// Whether the global allocator can actually provide that much memory is
+    /// another matter.
+    ///
+    /// # Panics
+    ///
+    /// This will panic on incorrect layouts.
+    #[track_caller]
+    pub fn max_capacity(layout: Layout) -> usize {
+        isize::MAX as usize / block_layout(layout).size()
+    }
+
+    /// Create a memory pool from a stored configuration.
+    ///
+    /// # Panics
+    ///
+    /// This will panic if the configuration does not describe a valid layout
+    /// and if the global allocator is out of memory.
+    #[track_caller]
+    pub fn from_config(config: PoolConfig) -> Self {
+        let layout = config.layout()
+            .expect("layout did not satisfy its constraints");
+        Self::new(config.capacity, layout)
+    }
+
+    /// Create a memory pool like [`MemoryPool::new`], but with an explicit
+    /// distance in bytes between consecutive blocks. This is useful to match
+    /// the element spacing of an externally defined array.
+    ///
+    /// The whole stride is available to each allocation, so requests up to
+    /// `stride` bytes fit in a block.
+    ///
+    /// # Panics
+    ///
+    /// This will panic if the stride is smaller than the block size or not a
+    /// multiple of the block alignment, on incorrect layouts and if the global
+    /// allocator is out of memory.
+    #[track_caller]
+    pub fn new_with_stride(capacity: usize, layout: Layout, stride: usize) -> Self {
+        let block = union_layout(layout, Layout::new::<Free>());
+
+        assert!(stride >= block.size(), "stride is smaller than the block size");
+        assert!(stride.is_multiple_of(block.align()), "stride is not a multiple of the alignment");
+
+        let block = Layout::from_size_align(stride, block.align())
+            .expect("layout did not satisfy its constraints");
 
+        Self::with_block_layout(capacity, layout, block, 1)
+    }
+
+    /// Create a memory pool from the requested layout and the final layout of
+    /// a block. The size of the block layout must be a multiple of its
+    /// alignment. The memory region is aligned to at least `array_align`.
+    #[track_caller]
+    pub(crate) fn with_block_layout(capacity: usize, requested: Layout, layout: Layout, array_align: usize) -> Self {
         // Get the layout for the array.
-        let (array, _) = layout.repeat(capacity)
+        let array = check_capacity(capacity, layout)
+            .and_then(|array| array.align_to(array_align).ok())
             .expect("layout did not satisfy its constraints");
 
         // Zeroed memory will be None for Option<NonNull<_>>
         let memory = Global.allocate_zeroed(array)
-            .unwrap_or_else(|_| alloc::alloc::handle_alloc_error(layout));
+            .unwrap_or_else(|_| alloc::alloc::handle_alloc_error(array));
+
+        Self::from_region(requested, layout, memory, Backing::Global(array))
+    }
+
+    /// Like [`MemoryPool::with_block_layout`], but return an error instead of
+    /// panicking when the region cannot be allocated.
+    pub(crate) fn try_with_block_layout(capacity: usize, requested: Layout, layout: Layout, array_align: usize) -> Result<Self, AllocError> {
+        let array = check_capacity(capacity, layout)
+            .and_then(|array| array.align_to(array_align).ok())
+            .ok_or(AllocError)?;
+
+        // Zeroed memory will be None for Option<NonNull<_>>
+        let memory = Global.allocate_zeroed(array)?;
+
+        Ok(Self::from_region(requested, layout, memory, Backing::Global(array)))
+    }
 
+    /// Create a memory pool that takes ownership of an existing buffer and
+    /// carves its blocks from it. The buffer is freed when the pool is
+    /// dropped.
+    ///
+    /// The first block starts at the first suitably aligned address in the
+    /// buffer, and the capacity is however many whole blocks fit after it.
+    /// The previous contents of the buffer are overwritten.
+    ///
+    /// # Panics
+    ///
+    /// This will panic on incorrect layouts.
+    #[track_caller]
+    pub fn from_boxed_slice(buffer: Box<[u8]>, layout: Layout) -> Self {
+        let block = block_layout(layout);
+        let buffer = NonNull::from(Box::leak(buffer));
+
+        // Skip ahead to the first address that satisfies the alignment.
+        let offset = buffer.as_mut_ptr().align_offset(block.align());
+        let capacity = buffer.len().saturating_sub(offset) / block.size();
+        let block_size = block.size();
+
+        let memory = unsafe {
+            let base = buffer.as_non_null_ptr().add(offset.min(buffer.len()));
+            // Zeroed memory will be None for Option<NonNull<_>>
+            base.write_bytes(0, capacity * block_size);
+            NonNull::slice_from_raw_parts(base, capacity * block_size)
+        };
+
+        Self::from_region(layout, block, memory, Backing::Boxed(buffer))
+    }
+
+    /// Create a memory pool like [`MemoryPool::new`], but allocate its memory
+    /// region from `backing` instead of the global allocator. This picks the
+    /// backing allocator at runtime, at the cost of a dynamic call when the
+    /// pool is created and dropped. The region is freed through `backing`.
+    ///
+    /// # Panics
+    ///
+    /// This will panic on incorrect layouts and if the backing allocator is
+    /// out of memory.
+    #[track_caller]
+    pub fn new_dyn(capacity: usize, layout: Layout, backing: Box<dyn Allocator>) -> Self {
+        let block = block_layout(layout);
+        let array = check_capacity(capacity, block)
+            .expect("layout did not satisfy its constraints");
+
+        // Zeroed memory will be None for Option<NonNull<_>>
+        let memory = backing.allocate_zeroed(array)
+            .unwrap_or_else(|_| alloc::alloc::handle_alloc_error(array));
+
+        Self::from_region(layout, block, memory, Backing::Dyn(backing, array))
+    }
+
+    /// Create a memory pool over a zeroed memory region, whose length is a
+    /// multiple of the block size.
+    fn from_region(requested: Layout, layout: Layout, memory: NonNull<[u8]>, backing: Backing) -> Self {
         // The next free element is the first entry in the allocated block.
         let base = memory.as_non_null_ptr();
 
         Self {
             layout,
+            requested,
             memory,
+            backing,
             next: base.into(),
+            len: Cell::new(0),
+            touched: Cell::new(0),
+            mode: Cell::new(Mode::FreeList),
+            name: None,
+            filter: None,
+            poisoned: Cell::new(false),
+            forgotten: 0,
+            forgotten_set: None,
+            relinked: RefCell::new(None),
+            quota: None,
+            low_water: None,
+            watchdog: None,
+            #[cfg(feature = "alloc-id")]
+            ids: (0..memory.len() / layout.size()).map(|_| Cell::new(0)).collect(),
+            #[cfg(feature = "alloc-id")]
+            last_id: Cell::new(0),
+            #[cfg(feature = "strict-layout")]
+            layouts: (0..memory.len() / layout.size()).map(|_| Cell::new(None)).collect(),
         }
     }
 
+    /// Create an independent pool with the same capacity and layout, whose
+    /// blocks are byte-for-byte copies of the blocks in this pool. Allocated
+    /// blocks stay allocated at the same indices, and the copy hands out its
+    /// free blocks in the same order.
+    ///
+    /// This is only meaningful for contents without pointers, since pointers
+    /// into the original are copied as they are. The copy keeps the name,
+    /// mode and allocation filter, but not the quota, the low water callback
+    /// or the watchdog.
+    ///
+    /// # Panics
+    ///
+    /// This will panic if the global allocator is out of memory. With the
+    /// `leak-check` feature, the copies of allocated blocks count as live, so
+    /// the copy panics when it is dropped unless it is [reset](Self::reset)
+    /// first.
+    pub fn deep_copy(&self) -> MemoryPool {
+        let array_align = match self.backing {
+            Backing::Global(array) => array.align(),
+            Backing::Boxed(_) => 1,
+            Backing::Dyn(_, array) => array.align(),
+        };
+        let mut copy = Self::with_block_layout(self.capacity(), self.requested, self.layout, array_align);
+
+        unsafe {
+            let base = copy.first_block();
+            core::ptr::copy_nonoverlapping(self.first_block().as_ptr(), base.as_ptr(), self.memory.len());
+        }
+
+        copy.next.set(self.next.get());
+        copy.len.set(self.len());
+        copy.touched.set(self.touched.get());
+        copy.mode.set(self.mode.get());
+        copy.name = self.name;
+        copy.filter = self.filter;
+        copy.poisoned.set(self.is_poisoned());
+        copy.forgotten = self.forgotten;
+        copy.forgotten_set = self.forgotten_set.clone();
+        copy.relinked = self.relinked.clone();
+        #[cfg(feature = "alloc-id")]
+        {
+            copy.ids = self.ids.clone();
+            copy.last_id.set(self.last_id.get());
+        }
+        #[cfg(feature = "strict-layout")]
+        {
+            copy.layouts = self.layouts.clone();
+        }
+
+        // The links still point into this pool. A corrupted free list
+        // cannot be followed, but a poisoned copy will not use it anyway.
+        if !self.is_poisoned() {
+            unsafe { copy.rebase_free_list(self.first_block()) };
+        }
+        copy
+    }
+
+    /// Configure a memory pool with options beyond [`MemoryPool::new`].
+    pub fn builder(capacity: usize, layout: Layout) -> PoolBuilder {
+        PoolBuilder::new(capacity, layout)
+    }
+
     /// The maximum number of entries this pool can contain.
     pub fn capacity(&self) -> usize {
         self.memory.len() / self.layout.size()
     }
 
+    /// The block at the lowest address, which is the base of the region.
+    pub fn first_block(&self) -> NonNull<u8> {
+        self.memory.as_non_null_ptr()
+    }
+
+    /// The block at the highest address. For a pool without capacity this is
+    /// the base of the (empty) region.
+    pub fn last_block(&self) -> NonNull<u8> {
+        let last = self.capacity().saturating_sub(1);
+        unsafe { self.first_block().add(last * self.layout.size()) }
+    }
+
+    /// The name given to this pool with [`PoolBuilder::name`], if any.
+    pub fn name(&self) -> Option<&'static str> {
+        self.name
+    }
+
+    /// The size of every block in bytes. This is at least the size of the
+    /// layout the pool was made for.
+    pub fn block_size(&self) -> usize {
+        self.layout.size()
+    }
+
+    /// The number of bytes in each block that are only there because a block
+    /// must be able to hold a free list link. This excludes alignment padding.
+    ///
+    /// When this is a large part of each block, a pool of larger elements (or
+    /// several elements per block) makes better use of the memory.
+    pub fn wasted_bytes_per_block(&self) -> usize {
+        core::mem::size_of::<Free>().saturating_sub(self.requested.size())
+    }
+
+    /// Whether the block size is set by the free list link rather than by the
+    /// requested layout, that is whether the requested size is smaller than a
+    /// pointer. See [`MemoryPool::wasted_bytes_per_block`] for how much.
+    pub fn uses_pointer_floor(&self) -> bool {
+        self.requested.size() < core::mem::size_of::<Free>()
+    }
+
+    /// The number of blocks that are currently allocated.
+    pub fn len(&self) -> usize {
+        self.len.get()
+    }
+
+    /// Check if no blocks are currently allocated.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The number of blocks that can still be allocated.
+    pub fn available(&self) -> usize {
+        self.capacity() - self.len() - self.forgotten
+    }
+
+    /// The number of bytes reserved for the blocks of this pool.
+    pub fn reserved_bytes(&self) -> usize {
+        self.memory.len()
+    }
+
+    /// The number of payload bytes that can still be allocated, counted in
+    /// the size of the layout the pool was made for.
+    ///
+    /// This is what remains usable, not what remains reserved: each free block
+    /// reserves [`MemoryPool::block_size`] bytes, which can be more than the
+    /// payload when the type is smaller than a pointer or needs padding.
+    pub fn available_bytes(&self) -> usize {
+        self.available() * self.requested.size()
+    }
+
+    /// Only allocate layouts for which the filter returns `true`, on top of
+    /// the usual size and alignment check. This catches types that happen to
+    /// fit a block but should not be allocated from this pool.
+    ///
+    /// Allocating by index or next to another block, which takes no layout,
+    /// checks the layout the pool was created for instead.
+    pub fn set_allocation_filter(&mut self, filter: fn(Layout) -> bool) {
+        self.filter = Some(filter);
+    }
+
+    /// Call `callback` whenever the number of available blocks drops below
+    /// `threshold`. It fires once on crossing the threshold, from
+    /// `allocate`, and only fires again after availability has risen back to
+    /// the threshold. This gives an early signal for backpressure.
+    pub fn set_low_water(&mut self, threshold: usize, callback: fn(&PoolMetrics)) {
+        let armed = Cell::new(self.available() >= threshold);
+        self.low_water = Some(LowWater { threshold, callback, armed });
+    }
+
+    /// Call `callback` when `threshold` blocks have been allocated in a row
+    /// without any deallocation in between. This points out allocation loops
+    /// that forget to free, before the pool is exhausted. It fires once per
+    /// streak, so panicking in the callback turns it into a strict check.
+    pub fn set_watchdog(&mut self, threshold: usize, callback: fn(&PoolMetrics)) {
+        self.watchdog = Some(Watchdog { threshold, callback, streak: Cell::new(0) });
+    }
+
+    /// A snapshot of the current usage of the pool.
+    pub fn metrics(&self) -> PoolMetrics {
+        PoolMetrics {
+            capacity: self.capacity(),
+            len: self.len(),
+            available: self.available(),
+            block_size: self.block_size(),
+        }
+    }
+
+    /// How scattered the free blocks are, as the share of them that lie in
+    /// the largest run of adjacent free blocks. This is 1.0 when all free
+    /// blocks are contiguous, or there are none, and approaches 0.0 as they
+    /// scatter. Finding the runs takes time linear in the capacity.
+    ///
+    /// The free list of a poisoned pool cannot be walked, so this is 0.0 for
+    /// one.
+    pub fn fragmentation(&self) -> f32 {
+        if self.is_poisoned() { return 0.0 }
+        let free = self.free_set();
+        let (mut total, mut run, mut largest) = (0, 0, 0);
+        for index in 0..self.capacity() {
+            if free.contains(index) {
+                total += 1;
+                run += 1;
+                largest = core::cmp::max(largest, run);
+            } else {
+                run = 0;
+            }
+        }
+
+        if total == 0 { return 1.0 }
+        largest as f32 / total as f32
+    }
+
+    /// Allocate a block and fill its start with a copy of `src`. The bytes
+    /// after `src` are left as they are.
+    ///
+    /// This returns `None` if `src` does not fit in a block or the pool is
+    /// exhausted. The block must be freed with `Layout::for_value(src)`.
+    pub fn alloc_copy(&self, src: &[u8]) -> Option<NonNull<[u8]>> {
+        let layout = Layout::for_value(src);
+        let block = self.allocate(layout).ok()?;
+
+        // The block is freshly allocated, so it cannot overlap with `src`.
+        unsafe {
+            core::ptr::copy_nonoverlapping(src.as_ptr(), block.as_mut_ptr(), src.len());
+        }
+        Some(block)
+    }
+
+    /// Allocate a block like [`Allocator::allocate`], but return `None` when
+    /// the allocation fails, for callers that simply drop what does not fit.
+    ///
+    /// A layout that does not fit in a block is a bug rather than a full pool,
+    /// so with debug assertions this panics on one.
+    pub fn allocate_or_none(&self, layout: Layout) -> Option<NonNull<[u8]>> {
+        debug_assert_eq!(self.layout, union_layout(self.layout, layout), "layout does not fit in a block of this pool");
+        self.allocate(layout).ok()
+    }
+
+    /// Allocate a block like [`Allocator::allocate`], and also return the
+    /// slack: the number of bytes in the block beyond the requested size,
+    /// which is `block_size - layout.size()`.
+    pub fn allocate_with_slack(&self, layout: Layout) -> Result<(NonNull<[u8]>, usize), AllocError> {
+        let block = self.allocate(layout)?;
+        Ok((block, block.len() - layout.size()))
+    }
+
+    /// Reduce the capacity and give the memory past the new capacity back to
+    /// the global allocator. All allocated blocks must already lie within the
+    /// new capacity; nothing is moved on their behalf.
+    ///
+    /// The allocator may move the region, which is why this needs unique
+    /// access: no allocation from the pool can be alive across the call.
+    ///
+    /// # Errors
+    ///
+    /// This errors without changing the pool if the new capacity is larger
+    /// than the current one, if an allocated block lies past it, if the pool
+    /// is poisoned, or if the pool does not own a region from the global
+    /// allocator. If only the global allocator fails to shrink the region,
+    /// this still succeeds: the capacity is reduced, but the memory is kept
+    /// until the pool is dropped.
+    pub fn shrink_capacity_to(&mut self, new_capacity: usize) -> Result<(), AllocError> {
+        let Backing::Global(old_layout) = self.backing else { return Err(AllocError) };
+        if new_capacity > self.capacity() || self.is_poisoned() { return Err(AllocError) }
+
+        let free = self.free_set();
+        if !(new_capacity..self.capacity()).all(|index| free.contains(index)) {
+            return Err(AllocError)
+        }
+
+        self.truncate_free_list(new_capacity);
+
+        let size = new_capacity * self.layout.size();
+        let old_base = self.first_block();
+        self.memory = NonNull::slice_from_raw_parts(old_base, size);
+
+        #[cfg(feature = "alloc-id")]
+        truncate_side_array(&mut self.ids, new_capacity);
+        #[cfg(feature = "strict-layout")]
+        truncate_side_array(&mut self.layouts, new_capacity);
+        self.touched.set(self.touched.get().min(new_capacity));
+        self.update_low_water();
+
+        // Only the size shrinks, so this layout is valid as well. The region
+        // stays valid if the allocator cannot shrink it, so keep it whole.
+        let Ok(new_layout) = Layout::from_size_align(size, old_layout.align()) else { return Ok(()) };
+        let Ok(memory) = (unsafe { Global.shrink(old_base, old_layout, new_layout) }) else { return Ok(()) };
+
+        self.memory = memory;
+        self.backing = Backing::Global(new_layout);
+        unsafe { self.rebase_free_list(old_base) };
+
+        Ok(())
+    }
+
+    /// Check the free list for corruption: every link must point at a block of
+    /// this pool, there must be no cycles, and it must contain exactly the
+    /// blocks that are not allocated. This takes time linear in the capacity.
+    ///
+    /// If the check fails, the pool is [poisoned](Self::is_poisoned).
+    pub fn validate(&self) -> bool {
+        let valid = self.free_list_is_valid();
+        if !valid {
+            self.poisoned.set(true);
+        }
+        valid
+    }
+
+    /// Capture the structure of the free list for debugging. Unlike
+    /// [`MemoryPool::validate`] this does not poison the pool, and a cycle or
+    /// a link that leads out of the region is recorded in the dump rather
+    /// than followed. The walk takes time linear in the capacity.
+    pub fn dump_structure(&self) -> PoolDump {
+        let base = self.first_block().as_ptr() as usize;
+        let end = unsafe { self.first_block().add(self.memory.len()) };
+        let mut dump = PoolDump {
+            base,
+            block_size: self.block_size(),
+            head: self.next.get().as_ptr() as usize,
+            free_nodes: Vec::new(),
+            tail: None,
+            len: self.len(),
+            cyclic: false,
+            invalid_link: None,
+        };
+
+        let mut visited = Bitset::new(self.capacity());
+        let mut block = self.next.get();
+
+        while block != end {
+            // Only read links from actual blocks.
+            let offset = (block.as_ptr() as usize).wrapping_sub(base);
+            if !self.contains(block) || !offset.is_multiple_of(self.layout.size()) {
+                dump.invalid_link = Some(block.as_ptr() as usize);
+                break
+            }
+
+            let index = self.index_of(block);
+            if visited.contains(index) {
+                dump.cyclic = true;
+                break
+            }
+            visited.insert(index);
+
+            match unsafe { *block.cast::<Free>().as_ref() } {
+                Some(redirect) => {
+                    dump.free_nodes.push(block.as_ptr() as usize);
+                    block = redirect;
+                }
+                None => {
+                    dump.tail = Some(block.as_ptr() as usize);
+                    break
+                }
+            }
+        }
+
+        dump
+    }
+
+    /// Check if corruption was detected by [`MemoryPool::validate`]. A
+    /// poisoned pool refuses all allocations and ignores deallocations, so the
+    /// damage cannot spread.
+    pub fn is_poisoned(&self) -> bool {
+        self.poisoned.get()
+    }
+
+    /// Clear the poisoned state. The corruption is still there, so this is
+    /// normally followed by a [reset](Self::reset).
+    pub fn clear_poison(&self) {
+        self.poisoned.set(false);
+    }
+
+    /// Reorder the free list so that blocks on pages that are already partly
+    /// in use are handed out first, and pages with no allocated blocks last.
+    /// Pages are taken to be 4 KiB, and a block belongs to the page its start
+    /// lies on. Within a page, blocks are handed out in address order.
+    ///
+    /// This does not move any allocations, so it frees no page by itself. It
+    /// keeps those free pages untouched by later allocations for as long as
+    /// the pool can. The untouched tail stays last and is not written to. It
+    /// takes time linear in the capacity, plus sorting the free blocks, so it
+    /// is meant for idle periods. Pools that are not in free list mode, or are
+    /// poisoned, are left alone.
+    pub fn consolidate_free(&mut self) {
+        self.end_bump_phase();
+        if self.mode.get() != Mode::FreeList || self.is_poisoned() { return }
+
+        let free = self.free_set();
+        let base = self.first_block().as_ptr() as usize;
+        let page_of = |index: usize| (base + index * self.layout.size()) / PAGE_SIZE - base / PAGE_SIZE;
+
+        // Count the allocated blocks on every page.
+        let pages = self.capacity().checked_sub(1).map_or(0, |last| page_of(last) + 1);
+        let mut used = alloc::vec![0usize; pages];
+        for index in (0..self.capacity()).filter(|&index| !free.contains(index)) {
+            used[page_of(index)] += 1;
+        }
+
+        // The untouched tail is entirely on free pages, so it already comes
+        // last. Leave its links alone, so its pages are not faulted in.
+        let tail = self.untouched_tail();
+        let tail_index = if self.contains(tail) { self.index_of(tail) } else { self.capacity() };
+
+        // The busiest pages come first.
+        let mut order: Vec<_> = (0..tail_index).filter(|&index| free.contains(index)).collect();
+        order.sort_by_key(|&index| (core::cmp::Reverse(used[page_of(index)]), index));
+
+        // Link the other free blocks explicitly, ending at the tail.
+        let mut successor = tail;
+        for &index in order.iter().rev() {
+            let block = self.index_to_ptr(index);
+            unsafe { *block.cast::<Free>().as_mut() = Some(successor) };
+            successor = block;
+        }
+        self.next.set(successor);
+    }
+
+    /// Allocate a zeroed block like [`Allocator::allocate_zeroed`], and also
+    /// return the slack as [`MemoryPool::allocate_with_slack`] does. The whole
+    /// block is zeroed, including the slack, so all of it can be used.
+    pub fn allocate_zeroed_with_slack(&self, layout: Layout) -> Result<(NonNull<[u8]>, usize), AllocError> {
+        let (block, slack) = self.allocate_with_slack(layout)?;
+        unsafe { block.as_mut_ptr().write_bytes(0, block.len()) };
+        Ok((block, slack))
+    }
+
+    /// Free all blocks at once, so the whole capacity can be allocated again.
+    ///
+    /// This takes time linear in the number of blocks handed out since the
+    /// last reset, as their free list links are cleared. Blocks that were
+    /// never handed out are left alone, so their pages are not faulted in.
+    pub fn reset(&mut self) {
+        let base = self.memory.as_non_null_ptr();
+
+        // Zeroed links mean the adjacent block is free next, which turns the
+        // whole region back into an untouched tail.
+        for index in 0..self.touched.get().min(self.capacity()) {
+            unsafe { *self.index_to_ptr(index).cast::<Free>().as_mut() = None };
+        }
+
+        self.next.set(base);
+        self.touched.set(0);
+        if let Some(quota) = &self.quota {
+            quota.give(self.len());
+        }
+        self.len.set(0);
+        self.forgotten = 0;
+        self.forgotten_set = None;
+        *self.relinked.get_mut() = None;
+        self.update_low_water();
+        if let Some(watchdog) = &self.watchdog {
+            watchdog.streak.set(0);
+        }
+
+        #[cfg(feature = "alloc-id")]
+        self.ids.iter().for_each(|id| id.set(0));
+        #[cfg(feature = "strict-layout")]
+        self.layouts.iter().for_each(|layout| layout.set(None));
+    }
+
+    /// Forget every freed block, so new allocations only come from the part of
+    /// the pool that was never allocated since the last reset. Allocated
+    /// blocks stay valid. This keeps freed memory, for example with sensitive
+    /// contents, from being handed out again.
+    ///
+    /// The forgotten blocks no longer count as [available](Self::available),
+    /// and only a [reset](Self::reset) reclaims them. Pools that are poisoned
+    /// or do not keep a free list are left alone.
+    pub fn drop_free_list(&mut self) {
+        if self.mode.get() != Mode::FreeList || self.is_poisoned() { return }
+
+        let mut forgotten = self.forgotten_set.take().unwrap_or_else(|| Bitset::new(self.capacity()));
+        let relinked = self.relinked.take();
+
+        // Remember every freed block on the way to the untouched tail, and
+        // link the never allocated ones back together.
+        let mut head = None;
+        let mut kept: Option<NonNull<u8>> = None;
+        let mut block = self.next.get();
+        while self.contains(block) {
+            let Some(redirect) = (unsafe { *block.cast::<Free>().as_ref() }) else { break };
+            let index = self.index_of(block);
+
+            if relinked.as_ref().is_some_and(|relinked| relinked.contains(index)) {
+                match kept {
+                    Some(kept) => unsafe { *kept.cast::<Free>().as_mut() = Some(block) },
+                    None => head = Some(block),
+                }
+                kept = Some(block);
+            } else {
+                forgotten.insert(index);
+                self.forgotten += 1;
+            }
+            block = redirect;
+        }
+        if let Some(kept) = kept {
+            unsafe { *kept.cast::<Free>().as_mut() = Some(block) };
+        }
+
+        self.forgotten_set = Some(forgotten);
+        *self.relinked.get_mut() = relinked;
+        self.next.set(head.unwrap_or(block));
+        self.update_low_water();
+    }
+
+    /// Touch every page of the next `blocks` blocks of the untouched tail, so
+    /// allocating them does not page fault. This is cheaper than prefaulting
+    /// the whole pool right before a batch of allocations. If fewer blocks
+    /// are left in the tail, only those are touched.
+    ///
+    /// Blocks freed back onto the free list were already touched, so they
+    /// are not counted. Touching is a volatile write of the byte already
+    /// there, which also makes the system back the page with memory of its
+    /// own rather than a shared zero page. Poisoned pools are left alone.
+    pub fn prefault_next(&self, blocks: usize) {
+        if self.is_poisoned() { return }
+        let start = self.untouched_tail();
+        if !self.contains(start) { return }
+
+        let tail = self.capacity() - self.index_of(start);
+        let len = blocks.min(tail) * self.layout.size();
+
+        // The first byte, then the first byte of every page after it.
+        let mut offset = 0;
+        while offset < len {
+            unsafe {
+                let byte = start.add(offset).as_ptr();
+                byte.write_volatile(byte.read_volatile());
+            }
+            let address = start.as_ptr() as usize + offset;
+            offset += PAGE_SIZE - address % PAGE_SIZE;
+        }
+    }
+
+    /// Allocate a block like [`Allocator::allocate`], but if the pool is
+    /// exhausted, [reset](Self::reset) it and try once more. This is for
+    /// arenas that are meant to be reset between iterations of a loop.
+    ///
+    /// This assumes every block allocated so far is dead. Unique access rules
+    /// out boxes that borrow the pool, but any raw pointer into a block is
+    /// left dangling by the reset, and its block may be handed out again.
+    /// Other failures, like a layout that does not fit or a used up quota, do
+    /// not reset the pool.
+    pub fn allocate_or_reset(&mut self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        if let Ok(block) = self.allocate(layout) { return Ok(block) }
+        if self.contains(self.next.get()) { return Err(AllocError) }
+
+        self.reset();
+        self.allocate(layout)
+    }
+
+    /// Run `cleanup` on every allocated block, then [reset](Self::reset) the
+    /// pool. The callback runs before anything is freed, so the pointers are
+    /// valid for the duration of the call.
+    ///
+    /// The allocated blocks of a poisoned pool cannot be told apart from the
+    /// free ones, so `cleanup` is not run on any of them.
+    ///
+    /// # Panics
+    ///
+    /// This will panic on a [bump only](PoolBuilder::bump_only) pool. Its
+    /// freed blocks still count as allocated, so `cleanup` would run again on
+    /// contents that were already dropped.
+    pub fn reset_with(&mut self, mut cleanup: impl FnMut(NonNull<u8>)) {
+        assert!(self.mode.get() != Mode::BumpOnly, "bump only pools cannot tell freed blocks apart");
+        self.iter_blocks()
+            .filter(|&(_, allocated)| allocated)
+            .for_each(|(block, _)| cleanup(block));
+        self.reset();
+    }
+
+    /// Allocate the block at the given index, if it is currently free.
+    ///
+    /// This walks (and may rewrite) the free list up to the requested block,
+    /// so it takes time linear in the capacity. It is meant for tests and for
+    /// reproducing exact memory layouts, not for hot paths. The block is freed
+    /// with the layout of the pool.
+    ///
+    /// # Errors
+    ///
+    /// This errors if the index is out of range, the block is already
+    /// allocated or the pool does not keep a free list (see
+    /// [`PoolBuilder::bump_only`] and [`PoolBuilder::stack`]).
+    pub fn allocate_at(&self, index: usize) -> Result<NonNull<u8>, AllocError> {
+        self.end_bump_phase();
+        if index >= self.capacity() || self.mode.get() != Mode::FreeList || self.is_poisoned() {
+            return Err(AllocError)
+        }
+        if !self.allows_requested() { return Err(AllocError) }
+        let target = self.index_to_ptr(index);
+
+        // Reserve the block from the quota, handing it back if we fail.
+        if !self.take_quota() { return Err(AllocError) }
+        let result = self.splice_out(target);
+        if result.is_err() {
+            self.give_quota();
+        }
+        result
+    }
+
+    /// Allocate a block next to `hint` if one is free, preferring the block
+    /// after it, to keep related entries close together. Otherwise this
+    /// allocates like [`Allocator::allocate`] does.
+    ///
+    /// This is best effort, and looking for a free neighbour takes time linear
+    /// in the capacity.
+    pub fn allocate_near(&self, hint: NonNull<u8>) -> Result<NonNull<u8>, AllocError> {
+        // A corrupted free list cannot be walked.
+        if self.is_poisoned() || !self.allows_requested() { return Err(AllocError) }
+
+        if self.contains(hint) {
+            let index = self.index_of(hint);
+            let free = self.free_set();
+
+            let neighbour = [index.checked_add(1), index.checked_sub(1)].into_iter()
+                .flatten()
+                .find(|&neighbour| free.contains(neighbour));
+
+            if let Some(block) = neighbour.and_then(|neighbour| self.allocate_at(neighbour).ok()) {
+                return Ok(block)
+            }
+        }
+
+        self.allocate(self.requested).map(NonNull::as_non_null_ptr)
+    }
+
+    /// Allocate `count` adjacent blocks as a single run that starts at an
+    /// address aligned to `align`. Blocks are laid out at a fixed stride, so
+    /// blocks after the first are only aligned as well if the block size is a
+    /// multiple of `align`.
+    ///
+    /// Every block of the run counts as a separate allocation, and is freed on
+    /// its own with the layout of the pool. This returns `None` if `count` is
+    /// zero, no aligned run of free blocks exists or the pool does not keep a
+    /// free list. Finding and claiming the run takes time linear in the
+    /// capacity for every block.
+    pub fn reserve_aligned_run(&self, count: usize, align: usize) -> Option<NonNull<[u8]>> {
+        if count == 0 || !align.is_power_of_two() { return None }
+        self.end_bump_phase();
+        if self.mode.get() != Mode::FreeList || self.is_poisoned() || !self.allows_requested() { return None }
+
+        let free = self.free_set();
+        let start = (0..=self.capacity().checked_sub(count)?).find(|&start| {
+            (self.index_to_ptr(start).as_ptr() as usize).is_multiple_of(align)
+                && (start..start + count).all(|index| free.contains(index))
+        })?;
+
+        for index in start..start + count {
+            if self.allocate_at(index).is_err() {
+                // Hand back the part of the run we already claimed.
+                for claimed in start..index {
+                    unsafe { self.deallocate(self.index_to_ptr(claimed), self.requested) };
+                }
+                return None
+            }
+        }
+
+        Some(NonNull::slice_from_raw_parts(self.index_to_ptr(start), count * self.layout.size()))
+    }
+
+    /// Remove the given block from the free list and claim it.
+    fn splice_out(&self, target: NonNull<u8>) -> Result<NonNull<u8>, AllocError> {
+        // The free block whose link points to the block we are visiting, or
+        // None if we are visiting the head of the free list.
+        let mut previous: Option<NonNull<u8>> = None;
+        let mut block = self.next.get();
+
+        while self.contains(block) {
+            let redirect = unsafe { *block.cast::<Free>().as_ref() };
+            let successor = redirect.unwrap_or_else(|| self.adjacent(block));
+
+            if block == target {
+                // Splice the target out of the free list.
+                match previous {
+                    Some(previous) => unsafe {
+                        *previous.cast::<Free>().as_mut() = Some(successor);
+                    },
+                    None => self.next.set(successor),
+                }
+
+                // Blocks claimed by index are freed with the pool's layout.
+                #[cfg(feature = "strict-layout")]
+                self.layouts[self.index_of(block)].set(Some(self.requested));
+
+                self.claim(block);
+                return Ok(block);
+            }
+
+            if redirect.is_none() {
+                // From here on the free list runs through the untouched tail
+                // in address order, so an earlier block cannot be free.
+                if target < block { return Err(AllocError) }
+
+                // Make the implicit link explicit, so the link into the
+                // target is stored somewhere we can rewrite.
+                unsafe { *block.cast::<Free>().as_mut() = Some(successor) };
+                self.relinked.borrow_mut()
+                    .get_or_insert_with(|| Bitset::new(self.capacity()))
+                    .insert(self.index_of(block));
+            }
+
+            previous = Some(block);
+            block = successor;
+        }
+
+        Err(AllocError)
+    }
+
+    /// Walk every block from the lowest to the highest address, along with
+    /// whether it is currently allocated. Blocks forgotten by
+    /// [`MemoryPool::drop_free_list`] are not allocated, but the freed blocks
+    /// of a [bump only](PoolBuilder::bump_only) pool are until its reset.
+    ///
+    /// The free blocks are collected once up front, so the whole walk takes
+    /// time linear in the capacity. The free list of a poisoned pool cannot
+    /// be walked, so this yields nothing for one.
+    pub fn iter_blocks(&self) -> impl Iterator<Item = (NonNull<u8>, bool)> + '_ {
+        let (free, blocks) = if self.is_poisoned() {
+            (Bitset::new(0), 0)
+        } else {
+            (self.free_set(), self.capacity())
+        };
+        let forgotten = self.forgotten_set.as_ref();
+        (0..blocks).map(move |index| {
+            let unused = free.contains(index) || forgotten.is_some_and(|forgotten| forgotten.contains(index));
+            (self.index_to_ptr(index), !unused)
+        })
+    }
+
+    /// Collect the indices of all free blocks by walking the free list.
+    fn free_set(&self) -> Bitset {
+        let mut free = Bitset::new(self.capacity());
+        let mut block = self.next.get();
+
+        while self.contains(block) {
+            let index = self.index_of(block);
+            match unsafe { *block.cast::<Free>().as_ref() } {
+                Some(redirect) => {
+                    free.insert(index);
+                    block = redirect;
+                }
+                // Everything from the untouched tail onwards is free.
+                None => {
+                    free.insert_from(index);
+                    break;
+                }
+            }
+        }
+
+        free
+    }
+
+    /// Walk the free list without trusting its links.
+    fn free_list_is_valid(&self) -> bool {
+        let mut free = 0;
+        let mut block = self.next.get();
+
+        // The free list may also end exactly one past the region.
+        let end = unsafe { self.first_block().add(self.memory.len()) };
+
+        while block != end {
+            // Only read links from actual blocks, and stop on cycles.
+            let offset = (block.as_ptr() as usize).wrapping_sub(self.first_block().as_ptr() as usize);
+            if !self.contains(block) || !offset.is_multiple_of(self.layout.size()) || free == self.capacity() {
+                return false
+            }
+
+            match unsafe { *block.cast::<Free>().as_ref() } {
+                Some(redirect) => {
+                    free += 1;
+                    block = redirect;
+                }
+                None => {
+                    free += self.capacity() - self.index_of(block);
+                    break;
+                }
+            }
+        }
+
+        free == self.available()
+    }
+
+    /// Remove all blocks at or past the given capacity from the free list, so
+    /// it ends at the block with that index instead.
+    fn truncate_free_list(&self, capacity: usize) {
+        let end = unsafe { self.first_block().add(capacity * self.layout.size()) };
+
+        // Point the link of the previous free block (or the head) at `target`.
+        let relink = |previous: Option<NonNull<u8>>, target| match previous {
+            Some(previous) => unsafe { *previous.cast::<Free>().as_mut() = Some(target) },
+            None => self.next.set(target),
+        };
+
+        let mut previous: Option<NonNull<u8>> = None;
+        let mut block = self.next.get();
+
+        while self.contains(block) {
+            let redirect = unsafe { *block.cast::<Free>().as_ref() };
+            let successor = redirect.unwrap_or_else(|| self.adjacent(block));
+
+            if self.index_of(block) < capacity {
+                // An untouched tail before the new end runs up to it by itself.
+                if redirect.is_none() { return }
+                previous = Some(block);
+            } else {
+                relink(previous, successor);
+            }
+            block = successor;
+        }
+
+        // The free list ran out past the new end, so end it there instead.
+        relink(previous, end);
+    }
+
+    /// Rewrite the free list after the memory region moved from `old_base` to
+    /// its current address.
+    ///
+    /// # Safety
+    ///
+    /// The free list must be the one for the region at `old_base`, moved along
+    /// with the rest of the contents.
+    unsafe fn rebase_free_list(&self, old_base: NonNull<u8>) {
+        let base = self.first_block();
+        if base == old_base { return }
+
+        // Links may point one past the region, so only compute the offset.
+        let rebase = |ptr: NonNull<u8>| {
+            let offset = ptr.as_ptr() as usize - old_base.as_ptr() as usize;
+            NonNull::new_unchecked(base.as_ptr().wrapping_add(offset))
+        };
+
+        self.next.set(rebase(self.next.get()));
+        let mut block = self.next.get();
+
+        while self.contains(block) {
+            let link = block.cast::<Free>().as_mut();
+            match *link {
+                Some(redirect) => {
+                    block = rebase(redirect);
+                    *link = Some(block);
+                }
+                None => break,
+            }
+        }
+    }
+
+    /// Classify a pointer relative to this pool: the start of one of its
+    /// blocks, somewhere inside a block, or outside the pool altogether. Like
+    /// `contains`, this cannot tell whether the block is allocated.
+    pub fn validate_ptr(&self, ptr: NonNull<u8>) -> PtrStatus {
+        if !self.contains(ptr) { return PtrStatus::Foreign }
+        let offset = ptr.as_ptr() as usize - self.memory.as_mut_ptr() as usize;
+        if offset.is_multiple_of(self.layout.size()) {
+            PtrStatus::Owned
+        } else {
+            PtrStatus::Interior
+        }
+    }
+
+    /// Get the index of the block at the given pointer. The pointer must be
+    /// contained in this pool.
+    fn index_of(&self, block: NonNull<u8>) -> usize {
+        debug_assert!(self.contains(block));
+        let offset = block.as_ptr() as usize - self.memory.as_mut_ptr() as usize;
+        offset / self.layout.size()
+    }
+
+    /// The allocation ID of the block's current occupant. Every allocation
+    /// gets a new, increasing ID, so a reused block can be told apart from its
+    /// previous occupant in logs and traces.
+    ///
+    /// This returns `None` if the pointer is not a currently allocated block
+    /// of this pool.
+    #[cfg(feature = "alloc-id")]
+    pub fn alloc_id_of(&self, ptr: NonNull<u8>) -> Option<u64> {
+        if !self.contains(ptr) { return None }
+        match self.ids[self.index_of(ptr)].get() {
+            0 => None,
+            id => Some(id),
+        }
+    }
+
+    /// Use up one block of the quota, if there is one. Returns whether the
+    /// allocation may go ahead.
+    fn take_quota(&self) -> bool {
+        self.quota.as_ref().is_none_or(Quota::take)
+    }
+
+    /// Give one block back to the quota, if there is one.
+    fn give_quota(&self) {
+        if let Some(quota) = &self.quota {
+            quota.give(1);
+        }
+    }
+
+    /// Bookkeeping for a block that was just taken off the free list.
+    fn claim(&self, block: NonNull<u8>) {
+        self.len.set(self.len.get() + 1);
+        if let Some(relinked) = self.relinked.borrow_mut().as_mut() {
+            relinked.remove(self.index_of(block));
+        }
+        self.touched.set(self.touched.get().max(self.index_of(block) + 1));
+        self.update_low_water();
+
+        if let Some(watchdog) = &self.watchdog {
+            let streak = watchdog.streak.get() + 1;
+            watchdog.streak.set(streak);
+            if streak == watchdog.threshold {
+                (watchdog.callback)(&self.metrics());
+            }
+        }
+
+        #[cfg(feature = "alloc-id")]
+        {
+            let id = self.last_id.get() + 1;
+            self.last_id.set(id);
+            self.ids[self.index_of(block)].set(id);
+        }
+        #[cfg(not(feature = "alloc-id"))]
+        let _ = block;
+    }
+
+    /// Bookkeeping for a block that was just returned to the free list.
+    fn release(&self, block: NonNull<u8>) {
+        self.len.set(self.len.get() - 1);
+        self.update_low_water();
+
+        if let Some(watchdog) = &self.watchdog {
+            watchdog.streak.set(0);
+        }
+
+        #[cfg(feature = "alloc-id")]
+        self.ids[self.index_of(block)].set(0);
+        #[cfg(not(feature = "alloc-id"))]
+        let _ = block;
+    }
+
+    /// Fire or re-arm the low water callback if availability crossed its
+    /// threshold.
+    fn update_low_water(&self) {
+        let Some(low_water) = &self.low_water else { return };
+
+        if self.available() >= low_water.threshold {
+            low_water.armed.set(true);
+        } else if low_water.armed.replace(false) {
+            (low_water.callback)(&self.metrics());
+        }
+    }
+
+    /// Get the pointer to the block at the given index. The index must be less
+    /// than the capacity.
+    fn index_to_ptr(&self, index: usize) -> NonNull<u8> {
+        debug_assert!(index < self.capacity());
+        unsafe { self.memory.as_non_null_ptr().add(index * self.layout.size()) }
+    }
+
+    /// Every block can hold any layout that fits the pool, so resizing an
+    /// allocation never moves it. Layouts that do not fit cannot be allocated
+    /// at all, so those fail and leave the block alone.
+    unsafe fn resize_in_place(&self, ptr: NonNull<u8>, old_layout: Layout, new_layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        debug_assert!(self.contains(ptr));
+        if self.layout != union_layout(self.layout, new_layout) { return Err(AllocError) }
+
+        // The block now holds the new layout, which it must be freed with.
+        #[cfg(feature = "strict-layout")]
+        if let Some(allocated) = self.layouts[self.index_of(ptr)].replace(Some(new_layout)) {
+            assert_eq!(allocated, old_layout, "block resized with a different layout than it was allocated with");
+        }
+        #[cfg(not(feature = "strict-layout"))]
+        let _ = old_layout;
+
+        Ok(NonNull::slice_from_raw_parts(self.rederive(ptr), self.layout.size()))
+    }
+
+    /// Check if the allocation filter lets the pool's own layout through.
+    fn allows_requested(&self) -> bool {
+        self.filter.is_none_or(|filter| filter(self.requested))
+    }
+
+    /// Get a pointer to the same address that may access the whole block. A
+    /// pointer handed back by a caller may only be derived from the value in
+    /// the block, while the free list link can be larger than that value.
+    fn rederive(&self, ptr: NonNull<u8>) -> NonNull<u8> {
+        self.memory.as_non_null_ptr().with_addr(ptr.addr())
+    }
+
+    /// Follow the free list to the start of the untouched tail. This is one
+    /// past the region if the tail is used up.
+    fn untouched_tail(&self) -> NonNull<u8> {
+        let mut block = self.next.get();
+        while self.contains(block) {
+            match unsafe { *block.cast::<Free>().as_ref() } {
+                Some(redirect) => block = redirect,
+                None => break,
+            }
+        }
+        block
+    }
+
+    /// Switch an adaptive pool over to its free list. Until now every block
+    /// came from the untouched tail, which already is a valid free list.
+    fn end_bump_phase(&self) {
+        if self.mode.get() == Mode::Adaptive {
+            self.mode.set(Mode::FreeList);
+        }
+    }
+
+    /// Get the block directly after the given block. This may be one past the
+    /// end of the memory region.
+    fn adjacent(&self, block: NonNull<u8>) -> NonNull<u8> {
+        unsafe { block.add(self.layout.size()) }
+    }
+
     /// Check if the given pointer is in this pools address range.
     /// It does NOT (and cannot) check whether the entry is allocated.
     fn contains(&self, ptr: NonNull<u8>) -> bool {
@@ -119,22 +1387,38 @@ unsafe impl Allocator for MemoryPool {
         // Check if given layout fits the layout requirements.
         if self.layout != union_layout(self.layout, layout) { return Err(AllocError) }
 
+        // A corrupted free list cannot be trusted.
+        if self.is_poisoned() { return Err(AllocError) }
+
+        // Check if the layout is allowed for this pool.
+        if self.filter.is_some_and(|filter| !filter(layout)) { return Err(AllocError) }
+
         // Check if we have run out of memory
         let block = self.next.get();
         if !self.contains(block) { return Err(AllocError) }
 
+        // Check if the shared budget allows another block.
+        if !self.take_quota() { return Err(AllocError) }
+
+        // Remember the exact layout, to check it on deallocation.
+        #[cfg(feature = "strict-layout")]
+        self.layouts[self.index_of(block)].set(Some(layout));
+
+        if self.mode.get() != Mode::FreeList {
+            // Freed blocks are not linked, so the next block is always
+            // adjacent.
+            self.next.set(self.adjacent(block));
+            self.claim(block);
+            return Ok(NonNull::slice_from_raw_parts(block, self.layout.size()));
+        }
+
         // Get the next allocation in the chain
         let redirect = unsafe { *block.cast::<Free>().as_ref() };
 
-        // Get the element adjecent to the current free one.
-        let adjacent = unsafe {
-            let adjacent = block.as_ptr().add(self.layout.size());
-            NonNull::new_unchecked(adjacent)
-        };
-
         // The next item is either the next on in the chain,
         // or the one adjacent if there was none.
-        self.next.set(redirect.unwrap_or(adjacent));
+        self.next.set(redirect.unwrap_or_else(|| self.adjacent(block)));
+        self.claim(block);
 
         // Construct the slice to the allocated block.
         let slice = NonNull::slice_from_raw_parts(block, self.layout.size());
@@ -146,20 +1430,180 @@ unsafe impl Allocator for MemoryPool {
         debug_assert_eq!(self.layout, union_layout(self.layout, layout));
         // Check if the given pointer is contained in the allocator.
         debug_assert!(self.contains(ptr));
+        let ptr = self.rederive(ptr);
+
+        // Check if the block is freed with exactly the layout it got.
+        #[cfg(feature = "strict-layout")]
+        if let Some(allocated) = self.layouts[self.index_of(ptr)].take() {
+            assert_eq!(allocated, layout, "block deallocated with a different layout than it was allocated with");
+        }
+
+        // Blocks are only reclaimed on reset, and a corrupted free list had
+        // better not grow.
+        if self.mode.get() == Mode::BumpOnly || self.is_poisoned() { return }
+
+        self.end_bump_phase();
+        if self.mode.get() == Mode::Stack {
+            // Only the most recently allocated block can be popped off.
+            assert!(self.adjacent(ptr) == self.next.get(), "stack pool freed out of order");
+
+            // Clear the link, so everything from here on is an untouched tail.
+            *ptr.cast::<Free>().as_mut() = None;
+            self.next.set(ptr);
+            self.release(ptr);
+            self.give_quota();
+            return;
+        }
 
         // Let this entry point to the next free slot
         *ptr.cast::<Free>().as_mut() = Some(self.next.get());
 
         // Let our next allocation be the one that was just freed
-        self.next.set(ptr.into());
+        self.next.set(ptr);
+        self.release(ptr);
+        self.give_quota();
+    }
+
+    unsafe fn grow(&self, ptr: NonNull<u8>, old_layout: Layout, new_layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        debug_assert!(new_layout.size() >= old_layout.size());
+        self.resize_in_place(ptr, old_layout, new_layout)
+    }
+
+    unsafe fn grow_zeroed(&self, ptr: NonNull<u8>, old_layout: Layout, new_layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        debug_assert!(new_layout.size() >= old_layout.size());
+        let block = self.resize_in_place(ptr, old_layout, new_layout)?;
+        block.as_non_null_ptr().add(old_layout.size()).write_bytes(0, block.len() - old_layout.size());
+        Ok(block)
+    }
+
+    unsafe fn shrink(&self, ptr: NonNull<u8>, old_layout: Layout, new_layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        debug_assert!(new_layout.size() <= old_layout.size());
+        self.resize_in_place(ptr, old_layout, new_layout)
     }
 }
 
 impl Drop for MemoryPool {
     fn drop(&mut self) {
-        // This exact layout was already created, so this cannot fail.
-        let (layout, _) = self.layout.repeat(self.capacity()).unwrap();
-        unsafe { alloc::alloc::dealloc(self.memory.cast().as_ptr(), layout) }
+        // Blocks that are still allocated die with the pool.
+        if let Some(quota) = &self.quota {
+            quota.give(self.len());
+        }
+
+        match self.backing {
+            Backing::Global(layout) => unsafe {
+                Global.deallocate(self.memory.as_non_null_ptr(), layout)
+            },
+            Backing::Boxed(buffer) => drop(unsafe { Box::from_raw(buffer.as_ptr()) }),
+            Backing::Dyn(ref allocator, layout) => unsafe {
+                allocator.deallocate(self.memory.as_non_null_ptr(), layout)
+            },
+        }
+
+        // Freed blocks of a bump only pool count as allocated until a reset,
+        // so we cannot tell leaks apart there.
+        #[cfg(feature = "leak-check")]
+        if self.mode.get() != Mode::BumpOnly && !self.is_empty() && !panicking() {
+            panic!("memory pool dropped with {} leaked blocks", self.len());
+        }
+    }
+}
+
+/// Check if the current thread is already unwinding, so a leak check does not
+/// turn a panic into an abort. Without `std` we cannot know.
+#[cfg(feature = "leak-check")]
+fn panicking() -> bool {
+    #[cfg(any(feature = "std", test))]
+    return std::thread::panicking();
+    #[cfg(not(any(feature = "std", test)))]
+    return false;
+}
+
+/// Where a pointer lies relative to a pool, see [`MemoryPool::validate_ptr`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PtrStatus {
+    /// The pointer is the start of a block in the pool.
+    Owned,
+    /// The pointer is outside the pool.
+    Foreign,
+    /// The pointer is inside the pool, but not at the start of a block.
+    Interior,
+}
+
+/// The capacity of an [auto tuned](MemoryPool::auto_tuned) pool without a
+/// previous peak.
+const DEFAULT_CAPACITY: usize = 64;
+
+/// The page size assumed where the pool cares about pages.
+pub(crate) const PAGE_SIZE: usize = 4096;
+
+/// How a pool reuses freed blocks.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Mode {
+    /// Freed blocks are pushed onto the free list.
+    FreeList,
+    /// Freed blocks are only reclaimed on reset.
+    BumpOnly,
+    /// Only the most recently allocated block can be freed.
+    Stack,
+    /// Allocate like [`Mode::BumpOnly`] until the first block is freed, then
+    /// switch to [`Mode::FreeList`] for good.
+    Adaptive,
+}
+
+/// The owner of the memory region of a pool.
+enum Backing {
+    /// Allocated from the global allocator with the given layout.
+    Global(Layout),
+    /// A boxed slice that the memory region was carved from.
+    Boxed(NonNull<[u8]>),
+    /// Allocated from a backing allocator chosen at runtime, with the given
+    /// layout.
+    Dyn(Box<dyn Allocator>, Layout),
+}
+
+impl fmt::Debug for MemoryPool {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MemoryPool")
+            .field("name", &self.name)
+            .field("capacity", &self.capacity())
+            .field("len", &self.len())
+            .field("block_size", &self.block_size())
+            .field("mode", &self.mode.get())
+            .finish()
+    }
+}
+
+impl fmt::Display for MemoryPool {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (len, capacity) = (self.len(), self.capacity());
+        // Integer percentage, so we do not need floating-point formatting.
+        let percent = (len * 100).checked_div(capacity).unwrap_or(0);
+
+        f.write_str("MemoryPool")?;
+        if let Some(name) = self.name {
+            write!(f, "({})", name)?;
+        }
+        write!(f, "{{ {}/{} blocks used ({}%), {} blocks, {} reserved }}",
+            len, capacity, percent, Bytes(self.layout.size()), Bytes(self.memory.len()))
+    }
+}
+
+/// Formats a number of bytes exactly, with a binary unit suffix.
+struct Bytes(usize);
+
+impl fmt::Display for Bytes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+
+        // Find the largest unit the size is a whole number of, so nothing is
+        // rounded away: 1536 bytes are 1536B rather than 1KiB.
+        let mut size = self.0;
+        let mut unit = 0;
+        while size >= 1024 && size.is_multiple_of(1024) && unit + 1 < UNITS.len() {
+            size /= 1024;
+            unit += 1;
+        }
+        write!(f, "{}{}", size, UNITS[unit])
     }
 }
 
@@ -167,7 +1611,41 @@ impl Drop for MemoryPool {
 /// chain of pointers in memory.
 type Free = Option<NonNull<u8>>;
 
+/// Shorten an array with an entry per block to the given capacity.
+#[cfg(any(feature = "alloc-id", feature = "strict-layout"))]
+fn truncate_side_array<T>(array: &mut Box<[T]>, capacity: usize) {
+    let mut vec = alloc::vec::Vec::from(core::mem::take(array));
+    vec.truncate(capacity);
+    *array = vec.into_boxed_slice();
+}
+
+/// Returns the layout of a region with the given number of blocks, or None if it
+/// would be too large.
+fn check_capacity(capacity: usize, block: Layout) -> Option<Layout> {
+    block.repeat(capacity).ok().map(|(array, _)| array)
+}
+
+/// Returns the layout of a block that can hold both the given layout and a
+/// free list link.
+#[track_caller]
+pub(crate) fn block_layout(layout: Layout) -> Layout {
+    try_block_layout(layout).expect("layout did not satisfy its constraints")
+}
+
+/// Like [`block_layout`], but returns `None` instead of panicking when the
+/// block would be too large.
+pub(crate) fn try_block_layout(layout: Layout) -> Option<Layout> {
+    let free = Layout::new::<Free>();
+    let size = core::cmp::max(layout.size(), free.size());
+    let align = core::cmp::max(layout.align(), free.align());
+    Layout::from_size_align(size, align).ok()
+        // Pad the layout to be multiples of the alignment. We use this
+        // property when calculating the next free entry.
+        .map(|layout| layout.pad_to_align())
+}
+
 /// Returns a new layout as if the given two layouts were put into a union.
+#[track_caller]
 fn union_layout(first: Layout, second: Layout) -> Layout {
     let size = core::cmp::max(first.size(), second.size());
     let align = core::cmp::max(first.align(), second.align());
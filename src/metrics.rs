@@ -0,0 +1,100 @@
+use crate::{DryRunPool, MemoryPool};
+
+/// A snapshot of how a [`MemoryPool`](crate::MemoryPool) is being used.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PoolMetrics {
+    /// The maximum number of entries in the pool.
+    pub capacity: usize,
+    /// The number of blocks that are allocated.
+    pub len: usize,
+    /// The number of blocks that can still be allocated.
+    pub available: usize,
+    /// The size of every block in bytes.
+    pub block_size: usize,
+}
+
+/// The usage of a pool, common to every kind of pool in this crate, so
+/// monitoring code can take any of them as a `&dyn PoolIntrospect`.
+pub trait PoolIntrospect {
+    /// The maximum number of entries in the pool.
+    fn capacity(&self) -> usize;
+
+    /// The number of blocks that are allocated.
+    fn len(&self) -> usize;
+
+    /// The size of every block in bytes.
+    fn block_size(&self) -> usize;
+
+    /// Check if no blocks are allocated.
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The number of blocks that can still be allocated.
+    fn available(&self) -> usize {
+        self.capacity() - self.len()
+    }
+
+    /// A snapshot of the current usage of the pool.
+    fn metrics(&self) -> PoolMetrics {
+        PoolMetrics {
+            capacity: self.capacity(),
+            len: self.len(),
+            available: self.available(),
+            block_size: self.block_size(),
+        }
+    }
+}
+
+impl PoolIntrospect for MemoryPool {
+    fn capacity(&self) -> usize {
+        self.capacity()
+    }
+
+    fn len(&self) -> usize {
+        self.len()
+    }
+
+    fn block_size(&self) -> usize {
+        self.block_size()
+    }
+
+    fn available(&self) -> usize {
+        self.available()
+    }
+}
+
+impl PoolIntrospect for DryRunPool {
+    fn capacity(&self) -> usize {
+        self.capacity()
+    }
+
+    fn len(&self) -> usize {
+        self.len()
+    }
+
+    fn block_size(&self) -> usize {
+        self.block_size()
+    }
+}
+
+/// A callback that fires when the number of available blocks drops below a
+/// threshold.
+pub(crate) struct LowWater {
+    /// The callback fires when fewer than this many blocks are available.
+    pub(crate) threshold: usize,
+    pub(crate) callback: fn(&PoolMetrics),
+    /// Whether the callback may fire on the next crossing. This debounces it
+    /// until availability rises back to the threshold.
+    pub(crate) armed: core::cell::Cell<bool>,
+}
+
+/// A callback that fires when many blocks are allocated without any being
+/// freed in between.
+pub(crate) struct Watchdog {
+    /// The callback fires when this many allocations happen in a row.
+    pub(crate) threshold: usize,
+    pub(crate) callback: fn(&PoolMetrics),
+    /// The number of allocations since the last deallocation.
+    pub(crate) streak: core::cell::Cell<usize>,
+}
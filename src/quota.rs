@@ -0,0 +1,38 @@
+use core::cell::Cell;
+
+use alloc::rc::Rc;
+
+/// A budget of blocks shared by several pools. Every block allocated from a
+/// pool with this quota uses up one unit, and freeing it gives the unit back,
+/// so together the pools never exceed the budget.
+///
+/// Clones refer to the same budget.
+#[derive(Clone, Debug)]
+pub struct Quota {
+    remaining: Rc<Cell<usize>>,
+}
+
+impl Quota {
+    /// Create a quota of the given number of blocks.
+    pub fn new(blocks: usize) -> Self {
+        Self { remaining: Rc::new(Cell::new(blocks)) }
+    }
+
+    /// The number of blocks that can still be allocated under this quota.
+    pub fn remaining(&self) -> usize {
+        self.remaining.get()
+    }
+
+    /// Use up one block, if the budget allows it.
+    pub(crate) fn take(&self) -> bool {
+        let remaining = self.remaining.get();
+        if remaining == 0 { return false }
+        self.remaining.set(remaining - 1);
+        true
+    }
+
+    /// Give back the given number of blocks.
+    pub(crate) fn give(&self, blocks: usize) {
+        self.remaining.set(self.remaining.get() + blocks);
+    }
+}
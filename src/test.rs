@@ -1,6 +1,7 @@
 use super::MemoryPool;
 use std::vec::Vec;
 use std::alloc::Layout;
+use std::ptr::NonNull;
 
 #[test]
 fn capacity_small() {
@@ -17,6 +18,7 @@ fn capacity_large() {
 }
 
 #[test]
+#[allow(clippy::needless_range_loop)]
 fn just_allocations() {
     let capacity = 2_usize.pow(8);
     let pool = MemoryPool::new(capacity, Layout::new::<usize>());
@@ -77,3 +79,1811 @@ fn allocate_smaller_block() {
 
     let _ = Box::new_in(5_u8, &pool);
 }
+
+#[test]
+fn explicit_stride() {
+    let capacity = 2_usize.pow(4);
+    let stride = 32;
+    let pool = MemoryPool::new_with_stride(capacity, Layout::new::<u64>(), stride);
+    assert_eq!(capacity, pool.capacity());
+
+    let first = Box::new_in(1_u64, &pool);
+    let second = Box::new_in(2_u64, &pool);
+
+    let first = &*first as *const u64 as usize;
+    let second = &*second as *const u64 as usize;
+    assert_eq!(stride, second - first);
+}
+
+#[test]
+#[should_panic]
+fn stride_too_small() {
+    MemoryPool::new_with_stride(1, Layout::new::<[u64; 2]>(), 8);
+}
+
+#[test]
+#[should_panic]
+fn stride_misaligned() {
+    MemoryPool::new_with_stride(1, Layout::new::<u64>(), 12);
+}
+
+#[test]
+fn display_stats() {
+    let capacity = 2_usize.pow(10);
+    let pool = MemoryPool::new(capacity, Layout::new::<[u64; 4]>());
+
+    let _vec: Vec<_> = (0..capacity/2).map(|_| Box::new_in([0_u64; 4], &pool)).collect();
+
+    let display = format!("{}", pool);
+    assert!(display.contains("512/1024 blocks used (50%)"), "{}", display);
+    assert!(display.contains("32B blocks"), "{}", display);
+    assert!(display.contains("32KiB reserved"), "{}", display);
+}
+
+#[test]
+fn display_exact_bytes() {
+    let pool = MemoryPool::new(64, Layout::new::<[u8; 24]>());
+    assert!(format!("{}", pool).contains("24B blocks, 1536B reserved"), "{}", pool);
+
+    let pool = MemoryPool::new(3 * 2_usize.pow(16), Layout::new::<[u8; 8]>());
+    assert!(format!("{}", pool).contains("1536KiB reserved"), "{}", pool);
+}
+
+#[test]
+fn array_of_one() {
+    let pool = MemoryPool::new(1, Layout::new::<u64>());
+
+    // `Vec` allocates with `Layout::array`, which must fit the pool.
+    let mut vec = Vec::<u64, _>::with_capacity_in(1, &pool);
+    vec.push(5);
+    assert_eq!(5, vec[0]);
+    assert_eq!(1, pool.len());
+}
+
+#[test]
+fn dry_run_peak() {
+    use super::DryRunPool;
+    use std::alloc::Allocator;
+
+    let layout = Layout::new::<usize>();
+    let pool = DryRunPool::new(8, layout);
+
+    // Allocate three, free two, allocate four: at most five are live.
+    let a = pool.allocate(layout).unwrap();
+    let b = pool.allocate(layout).unwrap();
+    let _c = pool.allocate(layout).unwrap();
+    unsafe {
+        pool.deallocate(a.as_non_null_ptr(), layout);
+        pool.deallocate(b.as_non_null_ptr(), layout);
+    }
+    let _rest: Vec<_> = (0..4).map(|_| pool.allocate(layout).unwrap()).collect();
+
+    assert_eq!(5, pool.len());
+    assert_eq!(5, pool.peak());
+    assert_eq!(7, pool.total());
+}
+
+#[test]
+fn dry_run_exhaustion() {
+    use super::DryRunPool;
+    use std::alloc::{Allocator, AllocError};
+
+    let pool = DryRunPool::new(2, Layout::new::<u32>());
+    let _a = pool.allocate(Layout::new::<u32>()).unwrap();
+    let _b = pool.allocate(Layout::new::<u32>()).unwrap();
+    assert_eq!(Err(AllocError), pool.allocate(Layout::new::<u32>()));
+    // A layout that does not fit in the block is rejected as well.
+    assert_eq!(Err(AllocError), DryRunPool::new(2, Layout::new::<u32>())
+        .allocate(Layout::new::<[u64; 2]>()));
+}
+
+#[test]
+#[cfg(target_pointer_width = "64")]
+fn pointer_floor_waste() {
+    let pool = MemoryPool::new(1, Layout::new::<u16>());
+    assert_eq!(6, pool.wasted_bytes_per_block());
+
+    let pool = MemoryPool::new(1, Layout::new::<[u64; 2]>());
+    assert_eq!(0, pool.wasted_bytes_per_block());
+}
+
+#[test]
+fn allocate_at_index() {
+    use std::alloc::{Allocator, AllocError};
+
+    let capacity = 2_usize.pow(4);
+    let layout = Layout::new::<usize>();
+    let mut pool = MemoryPool::new(capacity, layout);
+
+    // Allocate from the untouched tail, out of order.
+    assert_eq!(Ok(pool.index_to_ptr(5)), pool.allocate_at(5));
+    assert_eq!(Ok(pool.index_to_ptr(2)), pool.allocate_at(2));
+    assert_eq!(Ok(pool.index_to_ptr(0)), pool.allocate_at(0));
+
+    // Already allocated and out of range indices are rejected.
+    assert_eq!(Err(AllocError), pool.allocate_at(5));
+    assert_eq!(Err(AllocError), pool.allocate_at(capacity));
+
+    // Freed blocks can be picked out of the free list again.
+    unsafe { pool.deallocate(pool.index_to_ptr(2), layout) };
+    assert_eq!(Ok(pool.index_to_ptr(2)), pool.allocate_at(2));
+
+    // The rest of the pool is still handed out exactly once.
+    let rest: Vec<_> = (3..capacity).map(|_| pool.allocate(layout).unwrap()).collect();
+    assert_eq!(Err(AllocError), pool.allocate(layout));
+    assert_eq!(capacity, pool.len());
+
+    let mut indices: Vec<_> = rest.iter()
+        .map(|ptr| (ptr.as_mut_ptr() as usize - pool.index_to_ptr(0).as_ptr() as usize) / layout.size())
+        .collect();
+    indices.sort();
+    let expected: Vec<_> = [1, 3, 4].into_iter().chain(6..capacity).collect();
+    assert_eq!(expected, indices);
+
+    pool.reset();
+}
+
+#[test]
+#[cfg(feature = "serde")]
+fn config_round_trip() {
+    use super::PoolConfig;
+
+    let original = MemoryPool::new(2_usize.pow(6), Layout::new::<[u32; 3]>());
+    let config = PoolConfig::new(original.capacity(), Layout::new::<[u32; 3]>());
+
+    let json = serde_json::to_string(&config).unwrap();
+    let config: PoolConfig = serde_json::from_str(&json).unwrap();
+
+    let pool = MemoryPool::from_config(config);
+    assert_eq!(original.capacity(), pool.capacity());
+    assert_eq!(original.block_size(), pool.block_size());
+}
+
+#[test]
+#[cfg(feature = "serde")]
+fn config_rejects_bad_align() {
+    use super::PoolConfig;
+
+    let json = r#"{"capacity":16,"size":8,"align":3}"#;
+    assert!(serde_json::from_str::<PoolConfig>(json).is_err());
+}
+
+#[test]
+fn reset_partly_used() {
+    use std::alloc::{Allocator, AllocError};
+
+    let capacity = 2_usize.pow(8);
+    let layout = Layout::new::<usize>();
+    let mut pool = MemoryPool::new(capacity, layout);
+
+    // Only the front of the pool is touched, with links made explicit up to
+    // the block claimed out of the untouched tail.
+    let first = pool.allocate(layout).unwrap();
+    let second = pool.allocate(layout).unwrap();
+    unsafe { pool.deallocate(first.as_non_null_ptr(), layout) };
+    pool.allocate_at(10).unwrap();
+    unsafe { pool.deallocate(second.as_non_null_ptr(), layout) };
+
+    pool.reset();
+    for index in 0..capacity {
+        assert_eq!(pool.index_to_ptr(index), pool.allocate(layout).unwrap().as_non_null_ptr());
+    }
+    assert_eq!(Err(AllocError), pool.allocate(layout));
+    pool.reset();
+}
+
+#[test]
+fn bump_only_reset() {
+    use std::alloc::{Allocator, AllocError};
+
+    let capacity = 2_usize.pow(8);
+    let layout = Layout::new::<usize>();
+    let mut pool = MemoryPool::builder(capacity, layout).bump_only(true).build();
+
+    for _ in 0..2 {
+        let mut vec: Vec<_> = (0..capacity).map(|i| Box::new_in(i, &pool)).collect();
+
+        // Freed blocks are not reused before a reset.
+        vec.truncate(capacity / 2);
+        assert_eq!(capacity, pool.len());
+        assert_eq!(Err(AllocError), pool.allocate(layout));
+
+        for (i, elem) in vec.iter().enumerate() {
+            assert!(i == **elem)
+        }
+        drop(vec);
+
+        pool.reset();
+        assert!(pool.is_empty());
+    }
+}
+
+#[test]
+fn reset_after_frees() {
+    use std::alloc::{Allocator, AllocError};
+
+    let capacity = 2_usize.pow(8);
+    let layout = Layout::new::<usize>();
+    let mut pool = MemoryPool::new(capacity, layout);
+
+    let mut vec: Vec<_> = (0..capacity).map(|i| Box::new_in(i, &pool)).collect();
+    for i in (0..capacity/2).step_by(2) {
+        vec.swap_remove(i);
+    }
+    drop(vec);
+
+    pool.reset();
+    let _vec: Vec<_> = (0..capacity).map(|i| Box::new_in(i, &pool)).collect();
+    assert_eq!(Err(AllocError), pool.allocate(layout));
+}
+
+#[test]
+fn available_payload_bytes() {
+    let capacity = 2_usize.pow(4);
+    let pool = MemoryPool::new(capacity, Layout::new::<u8>());
+
+    let _vec: Vec<_> = (0..4).map(|i| Box::new_in(i as u8, &pool)).collect();
+
+    assert_eq!(capacity - 4, pool.available());
+    // One payload byte per block, even though each block reserves more.
+    assert_eq!(capacity - 4, pool.available_bytes());
+    assert!(pool.block_size() > 1);
+}
+
+#[test]
+fn named_pool() {
+    let pool = MemoryPool::builder(4, Layout::new::<usize>()).name("frame-arena").build();
+    assert_eq!(Some("frame-arena"), pool.name());
+
+    assert!(format!("{}", pool).starts_with("MemoryPool(frame-arena){"));
+    assert!(format!("{:?}", pool).contains("\"frame-arena\""));
+
+    let pool = MemoryPool::new(4, Layout::new::<usize>());
+    assert!(format!("{}", pool).starts_with("MemoryPool{"));
+}
+
+#[test]
+fn tiered_escalation() {
+    use super::TieredPool;
+    use std::alloc::{Allocator, AllocError};
+
+    let pool = TieredPool::new([
+        MemoryPool::new(2, Layout::new::<[u64; 8]>()),
+        MemoryPool::new(2, Layout::new::<u64>()),
+        MemoryPool::new(2, Layout::new::<[u64; 2]>()),
+    ]);
+    let sizes: Vec<_> = pool.tiers().iter().map(MemoryPool::block_size).collect();
+    assert_eq!(vec![8, 16, 64], sizes);
+
+    let introspect: &dyn crate::PoolIntrospect = &pool;
+    assert_eq!((6, 0, 6, 64), (introspect.capacity(), introspect.len(), introspect.available(), introspect.block_size()));
+
+    let small = Box::new_in(1_u8, &pool);
+    let medium = Box::new_in([2_u64; 2], &pool);
+    let large = Box::new_in([3_u64; 5], &pool);
+    for (tier, expected) in pool.tiers().iter().zip([1, 1, 1]) {
+        assert_eq!(expected, tier.len());
+    }
+
+    // Exhausting the smallest tier escalates to the next one.
+    let _filler = Box::new_in(4_u8, &pool);
+    let escalated = Box::new_in(5_u8, &pool);
+    assert_eq!(2, pool.tiers()[0].len());
+    assert_eq!(2, pool.tiers()[1].len());
+    assert_eq!((5, 1), (introspect.len(), introspect.available()));
+
+    // Nothing fits a request larger than every tier.
+    assert_eq!(Err(AllocError), pool.allocate(Layout::new::<[u64; 9]>()));
+
+    assert_eq!((1, [2, 2], [3; 5], 5), (*small, *medium, *large, *escalated));
+    drop((small, medium, large, escalated));
+    let lens: Vec<_> = pool.tiers().iter().map(MemoryPool::len).collect();
+    assert_eq!(vec![1, 0, 0], lens);
+}
+
+#[test]
+fn blocks_in_address_order() {
+    let capacity = 2_usize.pow(6);
+    let layout = Layout::new::<usize>();
+    let pool = MemoryPool::new(capacity, layout);
+
+    let mut vec: Vec<_> = (0..capacity / 2).map(|i| Box::new_in(i, &pool)).collect();
+    // Free every third block of the allocated half.
+    vec.retain(|elem| **elem % 3 != 0);
+
+    let blocks: Vec<_> = pool.iter_blocks().collect();
+    assert_eq!(capacity, blocks.len());
+
+    for (index, (ptr, allocated)) in blocks.into_iter().enumerate() {
+        assert_eq!(pool.index_to_ptr(index), ptr);
+        assert_eq!(index < capacity / 2 && index % 3 != 0, allocated);
+    }
+}
+
+#[test]
+#[cfg(target_pointer_width = "64")]
+fn format_without_allocating() {
+    use core::fmt::Write;
+
+    /// Writes into a fixed buffer, failing instead of growing.
+    struct Buffer {
+        bytes: [u8; 128],
+        len: usize,
+    }
+
+    impl Write for Buffer {
+        fn write_str(&mut self, s: &str) -> core::fmt::Result {
+            let end = self.len + s.len();
+            let dest = self.bytes.get_mut(self.len..end).ok_or(core::fmt::Error)?;
+            dest.copy_from_slice(s.as_bytes());
+            self.len = end;
+            Ok(())
+        }
+    }
+
+    let pool = MemoryPool::builder(2, Layout::new::<usize>()).name("fixed").build();
+    let _elem = Box::new_in(0_usize, &pool);
+
+    let mut buffer = Buffer { bytes: [0; 128], len: 0 };
+    core::write!(buffer, "{}", pool).unwrap();
+    let written = core::str::from_utf8(&buffer.bytes[..buffer.len]).unwrap();
+    assert_eq!("MemoryPool(fixed){ 1/2 blocks used (50%), 8B blocks, 16B reserved }", written);
+}
+
+#[test]
+fn boundary_blocks() {
+    let capacity = 2_usize.pow(4);
+    let pool = MemoryPool::new(capacity, Layout::new::<[u8; 24]>());
+
+    assert!(pool.contains(pool.first_block()));
+    assert!(pool.contains(pool.last_block()));
+    assert!(!pool.contains(unsafe { pool.last_block().add(pool.block_size()) }));
+
+    assert_eq!(pool.index_to_ptr(0), pool.first_block());
+    assert_eq!(pool.index_to_ptr(capacity - 1), pool.last_block());
+}
+
+#[test]
+fn allocation_filter() {
+    use std::alloc::{Allocator, AllocError};
+
+    let mut pool = MemoryPool::new(4, Layout::new::<u64>());
+    pool.set_allocation_filter(|layout| layout.align() != 4);
+
+    // Fits the block, but is vetoed by the filter.
+    assert_eq!(Err(AllocError), pool.allocate(Layout::new::<u32>()));
+    assert!(pool.allocate(Layout::new::<u64>()).is_ok());
+    assert!(pool.allocate(Layout::new::<u16>()).is_ok());
+    assert_eq!(2, pool.len());
+
+    pool.reset();
+}
+
+#[test]
+fn allocation_filter_by_index() {
+    use std::alloc::{Allocator, AllocError};
+
+    let layout = Layout::new::<u64>();
+    let mut pool = MemoryPool::new(4, layout);
+    let hint = pool.allocate(layout).unwrap().as_non_null_ptr();
+    pool.set_allocation_filter(|_| false);
+
+    // The neighbour of the hint is free, but the filter still applies.
+    assert_eq!(Err(AllocError), pool.allocate_near(hint));
+    assert_eq!(Err(AllocError), pool.allocate_at(2));
+    assert_eq!(None, pool.reserve_aligned_run(2, 8));
+    assert_eq!(1, pool.len());
+
+    pool.reset();
+}
+
+#[test]
+fn copy_into_block() {
+    let mut pool = MemoryPool::new(2, Layout::new::<[u8; 32]>());
+
+    let src: Vec<u8> = (0..20).collect();
+    let block = pool.alloc_copy(&src).unwrap();
+    assert_eq!(32, block.len());
+    assert_eq!(&src[..], unsafe { &block.as_ref()[..src.len()] });
+
+    // A source larger than a block does not fit.
+    assert_eq!(None, pool.alloc_copy(&[0; 33]));
+    assert_eq!(1, pool.len());
+
+    pool.reset();
+}
+
+#[test]
+#[cfg(feature = "alloc-id")]
+fn allocation_ids() {
+    use std::alloc::Allocator;
+
+    let layout = Layout::new::<usize>();
+    let mut pool = MemoryPool::new(4, layout);
+
+    let blocks: Vec<_> = (0..3).map(|_| pool.allocate(layout).unwrap().as_non_null_ptr()).collect();
+    let ids: Vec<_> = blocks.iter().map(|&ptr| pool.alloc_id_of(ptr).unwrap()).collect();
+    assert!(ids.windows(2).all(|pair| pair[0] < pair[1]));
+
+    // A freed block has no occupant, and its next occupant gets a new ID.
+    unsafe { pool.deallocate(blocks[1], layout) };
+    assert_eq!(None, pool.alloc_id_of(blocks[1]));
+
+    let reused = pool.allocate(layout).unwrap().as_non_null_ptr();
+    assert_eq!(blocks[1], reused);
+    assert!(pool.alloc_id_of(reused).unwrap() > ids[2]);
+
+    pool.reset();
+}
+
+#[test]
+fn reset_with_cleanup() {
+    use std::alloc::Allocator;
+
+    let layout = Layout::new::<usize>();
+    let mut pool = MemoryPool::new(2_usize.pow(4), layout);
+
+    let mut blocks: Vec<_> = (0..8).map(|_| pool.allocate(layout).unwrap().as_non_null_ptr()).collect();
+    unsafe { pool.deallocate(blocks.remove(3), layout) };
+    for (i, block) in blocks.iter().enumerate() {
+        unsafe { block.cast::<usize>().write(i) };
+    }
+
+    let len = pool.len();
+    let mut seen = Vec::new();
+    pool.reset_with(|block| seen.push((block, unsafe { block.cast::<usize>().read() })));
+
+    let expected: Vec<_> = blocks.into_iter().enumerate().map(|(i, block)| (block, i)).collect();
+    assert_eq!(len, seen.len());
+    assert_eq!(expected, seen);
+    assert!(pool.is_empty());
+}
+
+#[test]
+fn low_water_mark() {
+    use super::PoolMetrics;
+    use std::alloc::Allocator;
+    use std::cell::RefCell;
+
+    thread_local! {
+        static FIRED: RefCell<Vec<usize>> = const { RefCell::new(Vec::new()) };
+    }
+    fn record(metrics: &PoolMetrics) {
+        FIRED.with_borrow_mut(|fired| fired.push(metrics.available));
+    }
+    let fired = || FIRED.with_borrow(Vec::clone);
+
+    let layout = Layout::new::<usize>();
+    let mut pool = MemoryPool::new(8, layout);
+    pool.set_low_water(3, record);
+
+    // Allocating past the mark fires once, not on every allocation.
+    let mut blocks: Vec<_> = (0..7).map(|_| pool.allocate(layout).unwrap()).collect();
+    assert_eq!(vec![2], fired());
+
+    // Freeing back above the mark re-arms the callback without firing.
+    for block in blocks.drain(4..) {
+        unsafe { pool.deallocate(block.as_non_null_ptr(), layout) };
+    }
+    assert_eq!(vec![2], fired());
+
+    // Crossing again fires again.
+    blocks.extend((0..2).map(|_| pool.allocate(layout).unwrap()));
+    assert_eq!(vec![2, 2], fired());
+
+    pool.reset();
+}
+
+#[test]
+fn owned_buffer() {
+    use std::alloc::{Allocator, AllocError};
+
+    let layout = Layout::new::<u64>();
+    let buffer = vec![0xff_u8; 8 * 16 + 7].into_boxed_slice();
+    let pool = MemoryPool::from_boxed_slice(buffer, layout);
+
+    // At most one block is lost to aligning the start of the buffer.
+    assert!(pool.capacity() == 16 || pool.capacity() == 15);
+    assert_eq!(0, pool.first_block().as_ptr() as usize % layout.align());
+
+    let vec: Vec<_> = (0..pool.capacity() as u64).map(|i| Box::new_in(i, &pool)).collect();
+    assert_eq!(Err(AllocError), pool.allocate(layout));
+    for (i, elem) in vec.iter().enumerate() {
+        assert!(i as u64 == **elem)
+    }
+}
+
+#[test]
+#[cfg(feature = "strict-layout")]
+fn strict_layout_round_trip() {
+    let pool = MemoryPool::new(4, Layout::new::<u64>());
+    let _vec: Vec<_> = (0..4_u32).map(|i| Box::new_in(i, &pool)).collect();
+}
+
+#[test]
+#[cfg(feature = "strict-layout")]
+#[should_panic(expected = "different layout")]
+fn strict_layout_mismatch() {
+    use std::alloc::Allocator;
+
+    let pool = MemoryPool::new(4, Layout::new::<u64>());
+    let block = pool.allocate(Layout::new::<u32>()).unwrap();
+    // This layout fits the block, but is not what it was allocated with.
+    unsafe { pool.deallocate(block.as_non_null_ptr(), Layout::new::<u16>()) };
+}
+
+#[test]
+#[cfg(feature = "strict-layout")]
+fn strict_layout_claimed_by_index() {
+    use std::alloc::Allocator;
+
+    let layout = Layout::new::<u64>();
+    let pool = MemoryPool::new(4, layout);
+    let at = pool.allocate_at(2).unwrap();
+    let near = pool.allocate_near(at).unwrap();
+    let run = pool.reserve_aligned_run(1, 8).unwrap();
+
+    // Each is checked against the layout the pool was created for.
+    for block in [at, near, run.as_non_null_ptr()] {
+        unsafe { pool.deallocate(block, layout) };
+    }
+    assert!(pool.is_empty());
+}
+
+#[test]
+#[cfg(feature = "strict-layout")]
+#[should_panic(expected = "different layout")]
+fn strict_layout_mismatch_by_index() {
+    let pool = MemoryPool::new(4, Layout::new::<u64>());
+    let block = pool.allocate_at(2).unwrap();
+    unsafe { std::alloc::Allocator::deallocate(&pool, block, Layout::new::<u32>()) };
+}
+
+#[test]
+fn slack_of_small_request() {
+    let mut pool = MemoryPool::new(2, Layout::new::<[u8; 64]>());
+
+    let (block, slack) = pool.allocate_with_slack(Layout::new::<[u8; 10]>()).unwrap();
+    assert_eq!(64, block.len());
+    assert_eq!(54, slack);
+
+    pool.reset();
+}
+
+#[test]
+fn shrink_capacity() {
+    use std::alloc::{Allocator, AllocError};
+
+    let layout = Layout::new::<usize>();
+    let mut pool = MemoryPool::new(2_usize.pow(8), layout);
+
+    let blocks: Vec<_> = (0..2_usize.pow(6)).map(|_| pool.allocate(layout).unwrap()).collect();
+    // Leave free blocks both inside and past the new capacity.
+    for (i, block) in blocks.into_iter().enumerate() {
+        if i >= 16 || i % 2 == 1 {
+            unsafe { pool.deallocate(block.as_non_null_ptr(), layout) };
+        }
+    }
+
+    let reserved = pool.reserved_bytes();
+    // Shrinking below an allocated block is refused.
+    assert_eq!(Err(AllocError), pool.shrink_capacity_to(8));
+    assert_eq!(Err(AllocError), pool.shrink_capacity_to(2_usize.pow(9)));
+    assert_eq!(Ok(()), pool.shrink_capacity_to(32));
+
+    assert_eq!(32, pool.capacity());
+    assert!(pool.reserved_bytes() < reserved);
+
+    // Everything but the eight allocated blocks can be allocated, once.
+    let vec: Vec<_> = (0..24_usize).map(|i| Box::new_in(i, &pool)).collect();
+    assert_eq!(Err(AllocError), pool.allocate(layout));
+
+    let mut blocks: Vec<_> = vec.iter().map(|elem| &**elem as *const usize).collect();
+    blocks.sort();
+    blocks.dedup();
+    assert_eq!(24, blocks.len());
+    assert!(blocks.iter().all(|&ptr| pool.contains(NonNull::new(ptr as *mut u8).unwrap())));
+
+    drop(vec);
+    pool.reset();
+}
+
+#[test]
+fn shrink_owned_buffer() {
+    use std::alloc::AllocError;
+
+    let mut pool = MemoryPool::from_boxed_slice(vec![0; 64].into_boxed_slice(), Layout::new::<u8>());
+    assert_eq!(Err(AllocError), pool.shrink_capacity_to(1));
+}
+
+#[test]
+fn shared_quota() {
+    use super::Quota;
+    use std::alloc::{Allocator, AllocError};
+
+    let layout = Layout::new::<usize>();
+    let quota = Quota::new(6);
+    let first = MemoryPool::builder(4, layout).quota(quota.clone()).build();
+    let second = MemoryPool::builder(4, layout).quota(quota.clone()).build();
+
+    let a: Vec<_> = (0..4).map(|i| Box::new_in(i, &first)).collect();
+    let b: Vec<_> = (0..2).map(|i| Box::new_in(i, &second)).collect();
+    assert_eq!(0, quota.remaining());
+
+    // The second pool has room, but the shared budget is used up.
+    assert_eq!(Err(AllocError), second.allocate(layout));
+    assert_eq!(Err(AllocError), second.allocate_at(3));
+
+    // Freeing in one pool makes room in the other.
+    drop(a);
+    assert_eq!(4, quota.remaining());
+    let c: Vec<_> = (0..2).map(|i| Box::new_in(i, &second)).collect();
+    assert_eq!(2, quota.remaining());
+    drop(b);
+    assert_eq!(4, quota.remaining());
+
+    // Dropping a pool returns what it still held. The leak check would panic
+    // on the forgotten block, so a reset returns it there.
+    drop(c);
+    std::mem::forget(Box::new_in(0, &first));
+    #[cfg(feature = "leak-check")]
+    let first = {
+        let mut first = first;
+        first.reset();
+        first
+    };
+    drop(first);
+    assert_eq!(6, quota.remaining());
+}
+
+#[test]
+#[cfg(feature = "leak-check")]
+#[should_panic(expected = "dropped with 1 leaked blocks")]
+fn leak_check_outstanding() {
+    use std::alloc::Allocator;
+
+    let layout = Layout::new::<usize>();
+    let pool = MemoryPool::new(4, layout);
+    pool.allocate(layout).unwrap();
+}
+
+#[test]
+#[cfg(feature = "leak-check")]
+fn leak_check_all_freed() {
+    let pool = MemoryPool::new(4, Layout::new::<usize>());
+    let vec: Vec<_> = (0..4).map(|i| Box::new_in(i, &pool)).collect();
+    drop(vec);
+}
+
+#[test]
+fn allocate_next_to_hint() {
+    use std::alloc::Allocator;
+
+    let layout = Layout::new::<usize>();
+    let mut pool = MemoryPool::new(2_usize.pow(4), layout);
+
+    let blocks: Vec<_> = (0..8).map(|_| pool.allocate(layout).unwrap().as_non_null_ptr()).collect();
+    // Free a block in the middle, then one elsewhere so it heads the free list.
+    unsafe {
+        pool.deallocate(blocks[4], layout);
+        pool.deallocate(blocks[1], layout);
+    }
+
+    // The block after the hint is free, so it is preferred over the head.
+    assert_eq!(Ok(blocks[4]), pool.allocate_near(blocks[3]));
+    // Only the block before the hint is free.
+    assert_eq!(Ok(blocks[1]), pool.allocate_near(blocks[2]));
+    // No neighbour is free, so we fall back to the free list.
+    assert_eq!(Ok(pool.index_to_ptr(8)), pool.allocate_near(blocks[5]));
+    assert_eq!(9, pool.len());
+
+    pool.reset();
+}
+
+#[test]
+fn capacity_limit() {
+    use std::alloc::AllocError;
+
+    let layout = Layout::new::<u8>();
+    let max = MemoryPool::max_capacity(layout);
+    assert_eq!(isize::MAX as usize / std::mem::size_of::<usize>(), max);
+
+    assert_eq!(AllocError, MemoryPool::try_new(max + 1, layout).unwrap_err());
+    assert_eq!(16, MemoryPool::try_new(16, layout).unwrap().capacity());
+}
+
+#[test]
+fn poison_on_corruption() {
+    use std::alloc::{Allocator, AllocError};
+
+    let layout = Layout::new::<usize>();
+    let mut pool = MemoryPool::new(8, layout);
+
+    let blocks: Vec<_> = (0..4).map(|_| pool.allocate(layout).unwrap().as_non_null_ptr()).collect();
+    unsafe {
+        pool.deallocate(blocks[1], layout);
+        pool.deallocate(blocks[2], layout);
+    }
+    assert!(pool.validate());
+
+    // Let a free block link into the middle of another block.
+    unsafe { *blocks[2].cast::<Option<NonNull<u8>>>().as_mut() = Some(blocks[0].add(1)) };
+    assert!(!pool.validate());
+    assert!(pool.is_poisoned());
+    assert_eq!(Err(AllocError), pool.allocate(layout));
+    assert_eq!(Err(AllocError), pool.allocate_at(5));
+
+    pool.clear_poison();
+    pool.reset();
+    assert!(pool.validate());
+    let _vec: Vec<_> = (0..8).map(|i| Box::new_in(i, &pool)).collect();
+}
+
+#[test]
+fn detect_free_list_cycle() {
+    use std::alloc::Allocator;
+
+    let layout = Layout::new::<usize>();
+    let mut pool = MemoryPool::new(8, layout);
+
+    let blocks: Vec<_> = (0..4).map(|_| pool.allocate(layout).unwrap().as_non_null_ptr()).collect();
+    unsafe {
+        pool.deallocate(blocks[1], layout);
+        pool.deallocate(blocks[2], layout);
+        // Close the free list into a loop.
+        *blocks[1].cast::<Option<NonNull<u8>>>().as_mut() = Some(blocks[2]);
+    }
+    assert!(!pool.validate());
+
+    pool.clear_poison();
+    pool.reset();
+}
+
+#[test]
+fn poisoned_walks_bail_out() {
+    use std::alloc::Allocator;
+
+    let layout = Layout::new::<usize>();
+    let mut pool = MemoryPool::new(8, layout);
+
+    let blocks: Vec<_> = (0..4).map(|_| pool.allocate(layout).unwrap().as_non_null_ptr()).collect();
+    unsafe {
+        pool.deallocate(blocks[1], layout);
+        pool.deallocate(blocks[2], layout);
+        *blocks[1].cast::<Option<NonNull<u8>>>().as_mut() = Some(blocks[2]);
+    }
+    assert!(!pool.validate());
+
+    // None of these may follow the cycle.
+    assert!(pool.allocate_near(blocks[0]).is_err());
+    assert_eq!(0, pool.iter_blocks().count());
+    assert_eq!(0.0, pool.fragmentation());
+    pool.prefault_next(8);
+    assert!(pool.shrink_capacity_to(4).is_err());
+
+    let mut cleaned = 0;
+    pool.reset_with(|_| cleaned += 1);
+    assert_eq!(0, cleaned);
+    pool.clear_poison();
+}
+
+#[test]
+fn zeroed_slack() {
+    let mut pool = MemoryPool::new(1, Layout::new::<[u8; 48]>());
+
+    // Dirty the block first, so zeroing is observable.
+    let block = pool.alloc_copy(&[0xaa; 48]).unwrap();
+    pool.reset();
+
+    let (zeroed, slack) = pool.allocate_zeroed_with_slack(Layout::new::<[u8; 16]>()).unwrap();
+    assert_eq!(block, zeroed);
+    assert_eq!(32, slack);
+    assert!(unsafe { zeroed.as_ref() }.iter().all(|&byte| byte == 0));
+
+    pool.reset();
+}
+
+#[test]
+fn panic_location_is_caller() {
+    use std::cell::Cell;
+    use std::panic;
+
+    thread_local! {
+        static LINE: Cell<Option<u32>> = const { Cell::new(None) };
+    }
+
+    // Only record panics on this thread, other tests may panic concurrently.
+    let previous = panic::take_hook();
+    panic::set_hook(Box::new(|info| {
+        LINE.set(info.location().map(|location| location.line()));
+    }));
+
+    let capacity_line = line!() + 1;
+    let capacity = panic::catch_unwind(|| MemoryPool::new(usize::MAX, Layout::new::<u64>()));
+    let capacity_location = LINE.take();
+
+    let layout = Layout::from_size_align(isize::MAX as usize - 1, 1).unwrap();
+    let layout_line = line!() + 1;
+    let layout = panic::catch_unwind(|| MemoryPool::new(1, layout));
+    let layout_location = LINE.take();
+
+    let stride_line = line!() + 1;
+    let stride = panic::catch_unwind(|| MemoryPool::new_with_stride(1, Layout::new::<u64>(), 4));
+    let stride_location = LINE.take();
+
+    panic::set_hook(previous);
+
+    assert!(capacity.is_err() && layout.is_err() && stride.is_err());
+    assert_eq!(Some(capacity_line), capacity_location);
+    assert_eq!(Some(layout_line), layout_location);
+    assert_eq!(Some(stride_line), stride_location);
+}
+
+#[test]
+fn stack_in_order() {
+    use std::alloc::Allocator;
+
+    let layout = Layout::new::<usize>();
+    let pool = MemoryPool::builder(4, layout).stack(true).build();
+
+    let a = Box::new_in(1_usize, &pool);
+    let b = Box::new_in(2_usize, &pool);
+    let c = Box::new_in(3_usize, &pool);
+    let top = &*c as *const usize;
+    drop(c);
+
+    // The popped block is the next one handed out.
+    let c = pool.allocate(layout).unwrap();
+    assert_eq!(top, c.as_mut_ptr() as *const usize);
+    unsafe { pool.deallocate(c.as_non_null_ptr(), layout) };
+
+    drop(b);
+    drop(a);
+    assert!(pool.is_empty());
+    assert!(pool.validate());
+}
+
+#[test]
+#[should_panic(expected = "freed out of order")]
+fn stack_out_of_order() {
+    let pool = MemoryPool::builder(4, Layout::new::<usize>()).stack(true).build();
+
+    let b = Box::new_in(2_usize, &pool);
+    let c = Box::new_in(3_usize, &pool);
+    drop(b);
+    drop(c);
+}
+
+#[test]
+fn deep_copy_is_independent() {
+    use std::alloc::{Allocator, AllocError};
+
+    let layout = Layout::new::<u64>();
+    let mut pool = MemoryPool::new(8, layout);
+
+    let blocks: Vec<_> = (0..6_u64).map(|i| {
+        let block = pool.allocate(layout).unwrap().as_non_null_ptr().cast::<u64>();
+        unsafe { block.write(i * 10) };
+        block
+    }).collect();
+    unsafe { pool.deallocate(blocks[2].cast(), layout) };
+
+    let mut copy = pool.deep_copy();
+    assert_eq!(pool.len(), copy.len());
+    assert!(copy.validate());
+
+    // Mutating the copy leaves the original alone.
+    let offset = |block: NonNull<u64>| block.as_ptr() as usize - pool.first_block().as_ptr() as usize;
+    let copied = |block: NonNull<u64>| unsafe { copy.first_block().add(offset(block)).cast::<u64>() };
+    for &i in &[0, 1, 3, 4, 5] {
+        assert_eq!(i as u64 * 10, unsafe { copied(blocks[i]).read() });
+        unsafe { copied(blocks[i]).write(0) };
+        assert_eq!(i as u64 * 10, unsafe { blocks[i].read() });
+    }
+
+    // The copy hands out the same free blocks, but from its own region.
+    let reused = copy.allocate(layout).unwrap().as_non_null_ptr();
+    assert_eq!(copied(blocks[2]).cast(), reused);
+    let rest: Vec<_> = (0..2).map(|_| copy.allocate(layout).unwrap().as_non_null_ptr()).collect();
+    assert!(rest.iter().all(|&block| copy.contains(block)));
+    assert_eq!(Err(AllocError), copy.allocate(layout));
+    assert_eq!(5, pool.len());
+
+    copy.reset();
+    pool.reset();
+}
+
+#[test]
+fn watchdog_streak() {
+    use super::PoolMetrics;
+    use std::cell::Cell;
+
+    thread_local! {
+        static FIRED: Cell<usize> = const { Cell::new(0) };
+    }
+    fn record(_: &PoolMetrics) {
+        FIRED.set(FIRED.get() + 1);
+    }
+
+    let mut pool = MemoryPool::new(16, Layout::new::<usize>());
+    pool.set_watchdog(4, record);
+
+    // Interleaved frees keep the streak short.
+    for i in 0..8 {
+        drop(Box::new_in(i, &pool));
+    }
+    assert_eq!(0, FIRED.get());
+
+    // Allocating without freeing fires once the streak reaches the threshold.
+    let vec: Vec<_> = (0..6).map(|i| Box::new_in(i, &pool)).collect();
+    assert_eq!(1, FIRED.get());
+    drop(vec);
+}
+
+#[test]
+#[should_panic(expected = "allocation loop")]
+fn watchdog_strict() {
+    use super::PoolMetrics;
+
+    fn strict(metrics: &PoolMetrics) {
+        panic!("likely allocation loop, {} blocks allocated", metrics.len);
+    }
+
+    let mut pool = MemoryPool::new(16, Layout::new::<usize>());
+    pool.set_watchdog(4, strict);
+    let _vec: Vec<_> = (0..6).map(|i| Box::new_in(i, &pool)).collect();
+}
+
+#[test]
+fn validate_ptr_status() {
+    use super::PtrStatus;
+
+    let pool = MemoryPool::new(4, Layout::new::<u64>());
+    let block = Box::new_in(7u64, &pool);
+    let ptr = NonNull::from(&*block).cast::<u8>();
+    assert_eq!(PtrStatus::Owned, pool.validate_ptr(ptr));
+    assert_eq!(PtrStatus::Owned, pool.validate_ptr(pool.last_block()));
+
+    let interior = unsafe { ptr.add(3) };
+    assert_eq!(PtrStatus::Interior, pool.validate_ptr(interior));
+
+    let foreign = Box::new(7u64);
+    assert_eq!(PtrStatus::Foreign, pool.validate_ptr(NonNull::from(&*foreign).cast()));
+}
+
+#[test]
+fn page_aligned_region() {
+    let pool = MemoryPool::builder(3, Layout::new::<u16>())
+        .page_aligned(true)
+        .build();
+    assert_eq!(0, pool.first_block().as_ptr() as usize % 4096);
+
+    let copy = pool.deep_copy();
+    assert_eq!(0, copy.first_block().as_ptr() as usize % 4096);
+
+    let block = Box::new_in(5u16, &pool);
+    assert_eq!(pool.first_block(), NonNull::from(&*block).cast());
+}
+
+#[test]
+#[cfg(target_pointer_width = "64")]
+fn pointer_floor() {
+    assert!(MemoryPool::new(4, Layout::new::<u8>()).uses_pointer_floor());
+    assert!(!MemoryPool::new(4, Layout::new::<u64>()).uses_pointer_floor());
+}
+
+#[test]
+fn reject_zst() {
+    use std::alloc::Allocator;
+
+    let zst = Layout::new::<()>();
+    assert!(MemoryPool::builder(4, zst).reject_zst(true).try_build().is_err());
+    assert!(MemoryPool::builder(4, Layout::new::<u8>()).reject_zst(true).try_build().is_ok());
+
+    // Without the flag a zero sized pool still spends a block per allocation.
+    let mut pool = MemoryPool::builder(4, zst).try_build().unwrap();
+    for _ in 0..4 {
+        pool.allocate(zst).unwrap();
+    }
+    assert_eq!(4, pool.len());
+    assert!(pool.allocate(zst).is_err());
+    pool.reset();
+}
+
+#[test]
+fn builder_modes() {
+    let layout = Layout::new::<u32>();
+
+    // Turning one mode off leaves another one alone.
+    let pool = MemoryPool::builder(4, layout).stack(true).bump_only(false).build();
+    assert_eq!(super::Mode::Stack, pool.mode.get());
+    let pool = MemoryPool::builder(4, layout).adaptive(true).adaptive(false).build();
+    assert_eq!(super::Mode::FreeList, pool.mode.get());
+
+    assert!(MemoryPool::builder(4, layout).stack(true).bump_only(true).try_build().is_err());
+}
+
+#[test]
+#[should_panic(expected = "cannot be combined")]
+fn builder_conflicting_modes() {
+    MemoryPool::builder(4, Layout::new::<u32>()).adaptive(true).stack(true).build();
+}
+
+#[test]
+#[should_panic(expected = "zero sized layouts are rejected")]
+fn reject_zst_build() {
+    MemoryPool::builder(4, Layout::new::<()>()).reject_zst(true).build();
+}
+
+#[test]
+fn consolidate_free_pages() {
+    use std::alloc::Allocator;
+
+    // Four pages of 512 blocks each.
+    let layout = Layout::new::<u64>();
+    let mut pool = MemoryPool::builder(2048, layout).page_aligned(true).build();
+    let blocks: Vec<_> = (0..2048).map(|_| pool.allocate(layout).unwrap().as_non_null_ptr()).collect();
+
+    // Free all of page 0 except one block, then all of page 3, so the most
+    // recently freed blocks are on the fully free page.
+    for &block in blocks[1..512].iter().chain(&blocks[1536..]) {
+        unsafe { pool.deallocate(block, layout) };
+    }
+
+    let fully_free_pages = |pool: &MemoryPool| {
+        let used: Vec<_> = pool.iter_blocks().map(|(_, used)| used).collect();
+        used.chunks(512).filter(|page| page.iter().all(|used| !used)).count()
+    };
+    assert_eq!(1, fully_free_pages(&pool));
+
+    pool.consolidate_free();
+    assert!(pool.validate());
+    assert_eq!(1023, pool.available());
+
+    // New allocations now fill page 0 and leave page 3 alone.
+    for _ in 0..100 {
+        let block = pool.allocate(layout).unwrap().as_non_null_ptr();
+        assert!(blocks[..512].contains(&block));
+    }
+    assert_eq!(1, fully_free_pages(&pool));
+    pool.reset();
+}
+
+#[test]
+fn consolidate_keeps_tail_untouched() {
+    use std::alloc::Allocator;
+
+    let layout = Layout::new::<u64>();
+    let mut pool = MemoryPool::builder(1024, layout).page_aligned(true).build();
+    let first = pool.allocate(layout).unwrap().as_non_null_ptr();
+    let _second = pool.allocate(layout).unwrap();
+    unsafe { pool.deallocate(first, layout) };
+
+    let tail = |pool: &MemoryPool| pool.index_of(pool.untouched_tail());
+    let before = (pool.touched.get(), tail(&pool));
+    pool.consolidate_free();
+    assert_eq!(before, (pool.touched.get(), tail(&pool)));
+    assert!(pool.validate());
+
+    // Only the freed block is forgotten, the tail can still be allocated.
+    pool.drop_free_list();
+    assert_eq!(1022, pool.available());
+    assert!(pool.allocate(layout).is_ok());
+    pool.reset();
+}
+
+/// Drive the pool through the invariants documented on [`Allocator`].
+mod conformance {
+    use super::*;
+    use std::alloc::Allocator;
+
+    /// The block of a pool for `u64`s: eight bytes, aligned to eight.
+    fn pool() -> MemoryPool {
+        MemoryPool::new(8, Layout::new::<u64>())
+    }
+
+    #[test]
+    fn fits_requested_layout() {
+        let pool = pool();
+        for (size, align) in [(0, 1), (1, 1), (3, 1), (2, 2), (4, 4), (8, 8)] {
+            let layout = Layout::from_size_align(size, align).unwrap();
+            let block = pool.allocate(layout).unwrap();
+            assert!(block.len() >= size);
+            assert_eq!(0, block.as_mut_ptr() as usize % align);
+            unsafe { pool.deallocate(block.as_non_null_ptr(), layout) };
+        }
+        assert!(pool.is_empty());
+    }
+
+    #[test]
+    fn maximum_alignment_within_block() {
+        let layout = Layout::from_size_align(64, 64).unwrap();
+        let pool = MemoryPool::new(4, layout);
+        let blocks: Vec<_> = (0..4).map(|_| pool.allocate(layout).unwrap()).collect();
+        for block in &blocks {
+            assert_eq!(0, block.as_mut_ptr() as usize % 64);
+        }
+        for block in blocks {
+            unsafe { pool.deallocate(block.as_non_null_ptr(), layout) };
+        }
+    }
+
+    #[test]
+    fn rejects_layouts_that_do_not_fit() {
+        let pool = pool();
+        assert!(pool.allocate(Layout::from_size_align(9, 1).unwrap()).is_err());
+        assert!(pool.allocate(Layout::from_size_align(8, 16).unwrap()).is_err());
+        assert!(pool.is_empty());
+    }
+
+    #[test]
+    fn live_blocks_do_not_overlap() {
+        let pool = pool();
+        let layout = Layout::new::<u64>();
+        let mut blocks: Vec<_> = (0..8).map(|_| pool.allocate(layout).unwrap()).collect();
+        blocks.sort_by_key(|block| block.as_mut_ptr());
+        for pair in blocks.windows(2) {
+            assert!(pair[0].as_mut_ptr() as usize + pair[0].len() <= pair[1].as_mut_ptr() as usize);
+        }
+        for block in blocks {
+            unsafe { pool.deallocate(block.as_non_null_ptr(), layout) };
+        }
+    }
+
+    #[test]
+    fn allocate_zeroed_zeroes_the_block() {
+        let pool = pool();
+        let layout = Layout::new::<u64>();
+
+        // Dirty a block first, so zeroing is not just the fresh region.
+        let block = pool.allocate(layout).unwrap();
+        unsafe {
+            block.as_mut_ptr().write_bytes(0xAA, block.len());
+            pool.deallocate(block.as_non_null_ptr(), layout);
+        }
+
+        let block = pool.allocate_zeroed(layout).unwrap();
+        assert!(unsafe { block.as_ref() }.iter().all(|&byte| byte == 0));
+        unsafe { pool.deallocate(block.as_non_null_ptr(), layout) };
+    }
+
+    #[test]
+    fn grow_shrink_round_trip() {
+        let pool = pool();
+        let small = Layout::new::<u16>();
+        let large = Layout::new::<u64>();
+
+        let block = pool.allocate(small).unwrap().as_non_null_ptr();
+        unsafe { block.cast::<u16>().write(0xBEEF) };
+
+        let grown = unsafe { pool.grow(block, small, large).unwrap() };
+        assert!(grown.len() >= large.size());
+        assert_eq!(0xBEEF, unsafe { grown.cast::<u16>().read() });
+        unsafe { grown.as_mut_ptr().add(2).cast::<u16>().write(0xF00D) };
+
+        let shrunk = unsafe { pool.shrink(grown.as_non_null_ptr(), large, small).unwrap() };
+        assert!(shrunk.len() >= small.size());
+        assert_eq!(0xBEEF, unsafe { shrunk.cast::<u16>().read() });
+
+        unsafe { pool.deallocate(shrunk.as_non_null_ptr(), small) };
+        assert!(pool.is_empty());
+    }
+
+    #[test]
+    fn grow_zeroed_zeroes_the_new_bytes() {
+        let pool = pool();
+        let small = Layout::new::<u16>();
+        let large = Layout::new::<u64>();
+
+        let block = pool.allocate(small).unwrap().as_non_null_ptr();
+        unsafe { block.cast::<u16>().write(0xBEEF) };
+
+        let grown = unsafe { pool.grow_zeroed(block, small, large).unwrap() };
+        let bytes = unsafe { grown.as_ref() };
+        assert_eq!(0xBEEF, u16::from_ne_bytes([bytes[0], bytes[1]]));
+        assert!(bytes[2..].iter().all(|&byte| byte == 0));
+        unsafe { pool.deallocate(grown.as_non_null_ptr(), large) };
+    }
+
+    #[test]
+    fn grow_past_the_block_fails() {
+        let pool = pool();
+        let layout = Layout::new::<u64>();
+        let block = pool.allocate(layout).unwrap().as_non_null_ptr();
+
+        // The original block stays valid when growing fails.
+        assert!(unsafe { pool.grow(block, layout, Layout::new::<[u64; 2]>()) }.is_err());
+        assert_eq!(1, pool.len());
+        unsafe { pool.deallocate(block, layout) };
+    }
+
+    #[test]
+    fn by_ref_allocates_from_the_same_pool() {
+        let pool = pool();
+        let layout = Layout::new::<u64>();
+        let block = pool.by_ref().allocate(layout).unwrap();
+        assert_eq!(1, pool.len());
+        unsafe { Allocator::deallocate(&&pool, block.as_non_null_ptr(), layout) };
+        assert!(pool.is_empty());
+    }
+}
+
+#[test]
+fn adaptive_switches_on_first_free() {
+    let pool = MemoryPool::builder(8, Layout::new::<u32>()).adaptive(true).build();
+
+    // The burst is bumped through in address order.
+    let burst: Vec<_> = (0..4u32).map(|i| Box::new_in(i, &pool)).collect();
+    for (index, block) in burst.iter().enumerate() {
+        let expected = unsafe { pool.first_block().add(index * pool.block_size()) };
+        assert_eq!(expected, NonNull::from(&**block).cast());
+    }
+
+    // The first free switches to the free list, so the block is reused.
+    let mut burst = burst.into_iter();
+    let first = burst.next().unwrap();
+    let reused = NonNull::from(&*first).cast::<u8>();
+    drop(first);
+    let block = Box::new_in(10u32, &pool);
+    assert_eq!(reused, NonNull::from(&*block).cast());
+
+    // Interleaved use keeps the contents and counts intact.
+    let mut live: Vec<_> = burst.collect();
+    live.push(block);
+    for i in 0..16u32 {
+        live.push(Box::new_in(100 + i, &pool));
+        let old = live.remove(0);
+        drop(old);
+        assert_eq!(4, pool.len());
+        assert!(pool.validate());
+    }
+    let values: Vec<_> = live.iter().map(|value| **value).collect();
+    assert_eq!(vec![112, 113, 114, 115], values);
+}
+
+/// Drive the C interface through its symbols, as a C program would.
+#[cfg(feature = "capi")]
+mod capi {
+    use super::MemoryPool;
+
+    /// The pool as C sees it, an opaque struct.
+    #[repr(C)]
+    struct Pool {
+        _opaque: [u8; 0],
+    }
+
+    extern "C" {
+        fn mp_new(capacity: usize, size: usize, align: usize) -> *mut Pool;
+        fn mp_alloc(pool: *mut Pool) -> *mut u8;
+        fn mp_free(pool: *mut Pool, block: *mut u8);
+        fn mp_destroy(pool: *mut Pool);
+    }
+
+    #[test]
+    fn round_trip() {
+        unsafe {
+            let pool = mp_new(2, 24, 8);
+            assert!(!pool.is_null());
+
+            let first = mp_alloc(pool);
+            let second = mp_alloc(pool);
+            assert!(!first.is_null() && !second.is_null());
+            assert_eq!(0, first as usize % 8);
+            assert!(mp_alloc(pool).is_null());
+
+            first.write_bytes(0xAB, 24);
+            mp_free(pool, first);
+            assert_eq!(first, mp_alloc(pool));
+
+            // The second block is still allocated, which destroying allows.
+            mp_free(pool, first);
+            mp_destroy(pool);
+        }
+    }
+
+    #[test]
+    fn bad_inputs() {
+        unsafe {
+            assert!(mp_new(4, 8, 3).is_null());
+            assert!(mp_new(usize::MAX, 8, 8).is_null());
+            assert!(mp_new(1, isize::MAX as usize - 2, 1).is_null());
+
+            assert!(mp_alloc(core::ptr::null_mut()).is_null());
+            mp_free(core::ptr::null_mut(), core::ptr::null_mut());
+            mp_destroy(core::ptr::null_mut());
+
+            // Null, foreign and interior pointers are ignored.
+            let pool = mp_new(2, 8, 8);
+            let block = mp_alloc(pool);
+            let mut foreign = 0u64;
+            mp_free(pool, core::ptr::null_mut());
+            mp_free(pool, (&mut foreign as *mut u64).cast());
+            mp_free(pool, block.add(1));
+            assert_eq!(1, (*pool.cast::<MemoryPool>()).len());
+
+            mp_free(pool, block);
+            assert!((*pool.cast::<MemoryPool>()).is_empty());
+            mp_destroy(pool);
+        }
+    }
+}
+
+#[test]
+fn aligned_run() {
+    use super::Quota;
+
+    // Blocks of 16 bytes, so every other block starts at a multiple of 32.
+    let layout = Layout::from_size_align(16, 16).unwrap();
+    let pool = MemoryPool::new(16, layout);
+
+    // Occupy the first aligned run of three blocks.
+    let first = pool.allocate_at(1).unwrap();
+    let run = pool.reserve_aligned_run(3, 32).unwrap();
+    let start = run.as_mut_ptr();
+    assert_eq!(0, start as usize % 32);
+    assert_eq!(3 * pool.block_size(), run.len());
+    assert!(start > first.as_ptr());
+    assert_eq!(4, pool.len());
+
+    // Each block of the run is its own allocation.
+    for index in 0..3 {
+        let block = unsafe { NonNull::new_unchecked(start.add(index * pool.block_size())) };
+        assert_eq!(0, block.as_ptr() as usize % 16);
+        unsafe { std::alloc::Allocator::deallocate(&pool, block, layout) };
+    }
+    unsafe { std::alloc::Allocator::deallocate(&pool, first, layout) };
+
+    assert!(pool.reserve_aligned_run(17, 16).is_none());
+    assert!(pool.reserve_aligned_run(0, 16).is_none());
+
+    // A quota that runs out halfway hands the claimed blocks back.
+    let quota = Quota::new(2);
+    let pool = MemoryPool::builder(8, layout).quota(quota.clone()).build();
+    assert!(pool.reserve_aligned_run(3, 16).is_none());
+    assert!(pool.is_empty());
+    assert_eq!(2, quota.remaining());
+}
+
+#[test]
+fn drop_free_list_after_allocate_at() {
+    use std::alloc::Allocator;
+
+    let layout = Layout::new::<u64>();
+    let mut pool = MemoryPool::new(1024, layout);
+    let claimed = pool.allocate_at(1000).unwrap();
+    pool.drop_free_list();
+    assert_eq!(1023, pool.available());
+
+    let freed = pool.allocate(layout).unwrap().as_non_null_ptr();
+    unsafe { pool.deallocate(freed, layout) };
+
+    // Only the freed block goes, the blocks skipped over stay available.
+    pool.drop_free_list();
+    assert_eq!(1022, pool.available());
+    assert!(pool.validate());
+    let blocks: Vec<_> = (0..1022).map(|_| pool.allocate(layout).unwrap().as_non_null_ptr()).collect();
+    assert!(!blocks.contains(&freed) && !blocks.contains(&claimed));
+    pool.reset();
+}
+
+#[test]
+fn drop_free_list_uses_tail() {
+    use std::alloc::Allocator;
+
+    let layout = Layout::new::<u64>();
+    let mut pool = MemoryPool::new(8, layout);
+    let blocks: Vec<_> = (0..4).map(|_| pool.allocate(layout).unwrap().as_non_null_ptr()).collect();
+
+    // Free the middle two, the outer two stay allocated.
+    for &block in &blocks[1..3] {
+        unsafe { pool.deallocate(block, layout) };
+    }
+
+    pool.drop_free_list();
+    assert_eq!(2, pool.len());
+    assert_eq!(4, pool.available());
+    assert!(pool.validate());
+    assert_eq!(2, pool.iter_blocks().filter(|&(_, allocated)| allocated).count());
+
+    // Only the untouched tail is handed out.
+    for _ in 0..4 {
+        let block = pool.allocate(layout).unwrap().as_non_null_ptr();
+        assert!(block > blocks[3]);
+    }
+    assert!(pool.allocate(layout).is_err());
+
+    // Forgotten blocks were freed, so they are not cleaned up again.
+    let mut cleaned = 0;
+    pool.reset_with(|_| cleaned += 1);
+    assert_eq!(6, cleaned);
+    assert_eq!(8, pool.available());
+}
+
+#[test]
+fn dyn_backing() {
+    use std::alloc::{AllocError, Allocator, Global};
+    use std::rc::Rc;
+    use std::cell::Cell;
+
+    /// Global, but counting the regions it hands out that are still live.
+    struct Counting(Rc<Cell<isize>>);
+
+    unsafe impl Allocator for Counting {
+        fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+            self.0.set(self.0.get() + 1);
+            Global.allocate(layout)
+        }
+
+        unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+            self.0.set(self.0.get() - 1);
+            Global.deallocate(ptr, layout)
+        }
+    }
+
+    let pool = MemoryPool::new_dyn(4, Layout::new::<u32>(), Box::new(Global));
+    let values: Vec<_> = (0..4u32).map(|i| Box::new_in(i, &pool)).collect();
+    assert!(Box::try_new_in(4u32, &pool).is_err());
+    assert_eq!(vec![0, 1, 2, 3], values.iter().map(|value| **value).collect::<Vec<_>>());
+    drop(values);
+    drop(pool);
+
+    let live = Rc::new(Cell::new(0));
+    let pool = MemoryPool::new_dyn(4, Layout::new::<u32>(), Box::new(Counting(live.clone())));
+    assert_eq!(1, live.get());
+    drop(Box::new_in(1u32, &pool));
+    assert_eq!(1, live.get());
+    drop(pool);
+    assert_eq!(0, live.get());
+}
+
+#[test]
+fn allocate_or_none() {
+    let mut pool = MemoryPool::new(2, Layout::new::<u32>());
+    assert!(pool.allocate_or_none(Layout::new::<u32>()).is_some());
+    assert!(pool.allocate_or_none(Layout::new::<u16>()).is_some());
+    assert!(pool.allocate_or_none(Layout::new::<u32>()).is_none());
+    pool.reset();
+}
+
+#[test]
+#[cfg(debug_assertions)]
+#[should_panic(expected = "layout does not fit")]
+fn allocate_or_none_misfit() {
+    let pool = MemoryPool::new(2, Layout::new::<u32>());
+    pool.allocate_or_none(Layout::new::<[u64; 4]>());
+}
+
+#[test]
+#[cfg(all(target_os = "linux", not(miri)))]
+fn prefault_next_batch() {
+    use std::alloc::Allocator;
+
+    /// The minor page faults of this thread so far.
+    fn minor_faults() -> u64 {
+        let stat = std::fs::read_to_string("/proc/thread-self/stat").unwrap();
+        let fields = &stat[stat.rfind(')').unwrap() + 2..];
+        fields.split(' ').nth(7).unwrap().parse().unwrap()
+    }
+
+    // A fresh region of 64 pages, large enough to be mapped lazily. Over
+    // aligning it would have the system allocator zero it by hand.
+    let layout = Layout::new::<[u8; 64]>();
+    let mut pool = MemoryPool::new(4096, layout);
+    let batch = 16 * 4096 / pool.block_size();
+
+    let touch = |count: usize| {
+        let before = minor_faults();
+        for _ in 0..count {
+            let block = pool.allocate(layout).unwrap();
+            unsafe { block.as_mut_ptr().write_bytes(1, 64) };
+        }
+        minor_faults() - before
+    };
+
+    pool.prefault_next(batch);
+    let prefaulted = touch(batch);
+    let cold = touch(batch);
+
+    // Best effort: reading the fault count may fault on its own.
+    assert!(prefaulted < cold, "{prefaulted} faults after prefaulting, {cold} without");
+
+    // Asking for more than is left only touches the rest of the tail.
+    pool.prefault_next(usize::MAX);
+    pool.reset();
+}
+
+#[test]
+fn resize_in_place() {
+    use std::alloc::Allocator;
+
+    let pool = MemoryPool::new(2, Layout::new::<u64>());
+
+    // A reallocation to the same layout is the identity.
+    let (value, alloc) = Box::into_raw_with_allocator(Box::new_in(0xC0FFEE_u64, &pool));
+    let ptr = NonNull::new(value).unwrap().cast::<u8>();
+    let layout = Layout::new::<u64>();
+    let grown = unsafe { alloc.grow(ptr, layout, layout).unwrap() };
+    let shrunk = unsafe { alloc.shrink(ptr, layout, layout).unwrap() };
+    assert_eq!(ptr, grown.as_non_null_ptr());
+    assert_eq!(ptr, shrunk.as_non_null_ptr());
+    let value = unsafe { Box::from_raw_in(value, alloc) };
+    assert_eq!(0xC0FFEE, *value);
+    assert_eq!(1, pool.len());
+
+    // Growing a vector within its block keeps it in place.
+    let mut bytes = Vec::with_capacity_in(2, &pool);
+    bytes.extend_from_slice(&[1u8, 2]);
+    let before = bytes.as_ptr();
+    bytes.reserve_exact(6);
+    assert_eq!(before, bytes.as_ptr());
+    bytes.extend_from_slice(&[3, 4, 5, 6]);
+    assert_eq!(&[1, 2, 3, 4, 5, 6], &bytes[..]);
+    assert_eq!(2, pool.len());
+}
+
+#[test]
+fn introspect_any_pool() {
+    use super::{DryRunPool, PoolIntrospect};
+    use std::alloc::Allocator;
+
+    let layout = Layout::new::<u64>();
+    let pool = MemoryPool::new(8, layout);
+    let dry_run = DryRunPool::new(16, layout);
+
+    let _block = Box::new_in(1u64, &pool);
+    let _ghosts = [dry_run.allocate(layout).unwrap(), dry_run.allocate(layout).unwrap()];
+
+    let pools: Vec<&dyn PoolIntrospect> = vec![&pool, &dry_run];
+    let report: Vec<_> = pools.iter().map(|pool| (pool.len(), pool.available(), pool.metrics())).collect();
+
+    assert_eq!((1, 7), (report[0].0, report[0].1));
+    assert_eq!((2, 14), (report[1].0, report[1].1));
+    assert_eq!(pool.metrics(), report[0].2);
+    assert_eq!(8, report[1].2.block_size);
+}
+
+/// Run a critical section pool against a simulated critical section, which
+/// only records when it is held.
+#[cfg(feature = "critical-section")]
+mod critical_section_pool {
+    use super::*;
+    use crate::CriticalSectionPool;
+    use std::cell::Cell;
+
+    thread_local! {
+        static DEPTH: Cell<usize> = const { Cell::new(0) };
+        static ACQUIRED: Cell<usize> = const { Cell::new(0) };
+    }
+
+    struct Simulated;
+    critical_section::set_impl!(Simulated);
+
+    unsafe impl critical_section::Impl for Simulated {
+        unsafe fn acquire() {
+            DEPTH.set(DEPTH.get() + 1);
+            ACQUIRED.set(ACQUIRED.get() + 1);
+        }
+
+        unsafe fn release(_: ()) {
+            DEPTH.set(DEPTH.get() - 1);
+        }
+    }
+
+    fn assert_sync<T: Sync>() {}
+
+    #[test]
+    fn guards_operations() {
+        assert_sync::<CriticalSectionPool>();
+
+        // The filter runs in the middle of allocating.
+        let mut pool = MemoryPool::new(4, Layout::new::<u32>());
+        pool.set_allocation_filter(|_| DEPTH.get() > 0);
+        let pool = CriticalSectionPool::new(pool);
+
+        let first = Box::new_in(1u32, &pool);
+        assert_eq!(1, ACQUIRED.get());
+        let second = Box::new_in(2u32, &pool);
+        assert_eq!(2, ACQUIRED.get());
+        assert_eq!(0, DEPTH.get());
+
+        drop(first);
+        assert_eq!(3, ACQUIRED.get());
+        assert_eq!(1, pool.with(MemoryPool::len));
+        assert_eq!(2, *second);
+        drop(second);
+
+        assert!(pool.into_inner().is_empty());
+    }
+
+    #[test]
+    fn introspect() {
+        let pool = CriticalSectionPool::new(MemoryPool::new(4, Layout::new::<u32>()));
+        let _value = Box::new_in(1u32, &pool);
+
+        let before = ACQUIRED.get();
+        let metrics = (&pool as &dyn crate::PoolIntrospect).metrics();
+        assert_eq!(before + 1, ACQUIRED.get());
+        assert_eq!(pool.with(MemoryPool::metrics), metrics);
+        assert_eq!(3, crate::PoolIntrospect::available(&pool));
+    }
+
+    #[test]
+    #[should_panic(expected = "quota")]
+    fn rejects_quota() {
+        let pool = MemoryPool::builder(4, Layout::new::<u32>()).quota(crate::Quota::new(4)).build();
+        CriticalSectionPool::new(pool);
+    }
+}
+
+#[test]
+fn fragmentation() {
+    let mut pool = MemoryPool::new(8, Layout::new::<u64>());
+    assert_eq!(1.0, pool.fragmentation());
+
+    let mut blocks: Vec<_> = (0..8u64).map(|i| Box::new_in(i, &pool)).collect();
+    assert_eq!(1.0, pool.fragmentation());
+
+    // Every other block free: each run holds one of the four free blocks.
+    let mut index = 0;
+    blocks.retain(|_| { index += 1; index % 2 == 0 });
+    assert_eq!(0.25, pool.fragmentation());
+
+    // Two adjacent free blocks out of five.
+    blocks.remove(0);
+    assert_eq!(0.6, pool.fragmentation());
+
+    drop(blocks);
+    assert_eq!(1.0, pool.fragmentation());
+    pool.reset();
+}
+
+#[test]
+fn auto_tuned_capacity() {
+    let layout = Layout::new::<u32>();
+    assert_eq!(150, MemoryPool::auto_tuned(layout, Some(100), 0.5).capacity());
+    assert_eq!(8, MemoryPool::auto_tuned(layout, Some(7), 0.1).capacity());
+    assert_eq!(120, MemoryPool::auto_tuned(layout, Some(100), 0.2).capacity());
+    assert_eq!(110, MemoryPool::auto_tuned(layout, Some(100), 0.1).capacity());
+    assert_eq!(10, MemoryPool::auto_tuned(layout, Some(10), 0.0).capacity());
+    assert_eq!(10, MemoryPool::auto_tuned(layout, Some(10), -1.0).capacity());
+    assert_eq!(0, MemoryPool::auto_tuned(layout, Some(0), 0.5).capacity());
+    assert_eq!(64, MemoryPool::auto_tuned(layout, None, 0.5).capacity());
+}
+
+#[test]
+#[cfg(not(miri))]
+fn auto_tuned_large_peak() {
+    let layout = Layout::new::<u8>();
+    assert_eq!(16_777_217, MemoryPool::auto_tuned(layout, Some(16_777_217), 0.0).capacity());
+
+    // The headroom has a resolution of a millionth.
+    assert_eq!(10_000_000, MemoryPool::auto_tuned(layout, Some(10_000_000), 4e-7).capacity());
+    assert_eq!(10_000_010, MemoryPool::auto_tuned(layout, Some(10_000_000), 1.4e-6).capacity());
+}
+
+#[test]
+#[should_panic(expected = "auto tuned capacity overflow")]
+fn auto_tuned_infinite_headroom() {
+    MemoryPool::auto_tuned(Layout::new::<u32>(), Some(1), f32::INFINITY);
+}
+
+#[test]
+fn allocate_or_reset() {
+    use std::alloc::Allocator;
+
+    let layout = Layout::new::<u32>();
+    let mut pool = MemoryPool::new(4, layout);
+    for _ in 0..4 {
+        pool.allocate(layout).unwrap();
+    }
+    assert!(pool.allocate(layout).is_err());
+
+    // The full arena is reset, and the retry gets the first block.
+    let block = pool.allocate_or_reset(layout).unwrap();
+    assert_eq!(pool.first_block(), block.as_non_null_ptr());
+    assert_eq!(1, pool.len());
+
+    // A layout that does not fit never resets.
+    assert!(pool.allocate_or_reset(Layout::new::<[u64; 2]>()).is_err());
+    assert_eq!(1, pool.len());
+    pool.reset();
+}
+
+/// Allocate through the `allocator-api2` collections.
+#[cfg(feature = "allocator-api2")]
+mod allocator_api2_compat {
+    use super::*;
+    use allocator_api2::boxed::Box;
+    use allocator_api2::vec::Vec;
+
+    #[test]
+    fn boxes_and_vectors() {
+        let pool = MemoryPool::new(4, Layout::new::<[u32; 4]>());
+
+        let value = Box::new_in(7u32, &pool);
+        assert_eq!(7, *value);
+        assert_eq!(1, pool.len());
+
+        // Growing within the block stays in place.
+        let mut values = Vec::with_capacity_in(1, &pool);
+        values.push(1u32);
+        let before = values.as_ptr();
+        values.extend([2, 3, 4]);
+        assert_eq!(before, values.as_ptr());
+        assert_eq!(&[1, 2, 3, 4], &values[..]);
+        assert_eq!(2, pool.len());
+
+        drop(value);
+        drop(values);
+        assert!(pool.is_empty());
+    }
+}
+
+#[test]
+fn dump_structure() {
+    use std::alloc::Allocator;
+
+    let layout = Layout::new::<u64>();
+    let pool = MemoryPool::new(4, layout);
+    let blocks: Vec<_> = (0..3).map(|_| pool.allocate(layout).unwrap().as_non_null_ptr()).collect();
+    unsafe {
+        pool.deallocate(blocks[0], layout);
+        pool.deallocate(blocks[2], layout);
+    }
+
+    // The most recently freed block comes first, then the untouched tail.
+    let dump = pool.dump_structure();
+    let address = |block: NonNull<u8>| block.as_ptr() as usize;
+    assert_eq!(address(blocks[2]), dump.head);
+    assert_eq!(vec![address(blocks[2]), address(blocks[0])], dump.free_nodes);
+    assert_eq!(Some(address(pool.last_block())), dump.tail);
+    assert_eq!(1, dump.len);
+    assert!(!dump.cyclic);
+    assert_eq!(None, dump.invalid_link);
+    assert!(pool.validate());
+
+    #[cfg(feature = "serde")]
+    {
+        let json = serde_json::to_string(&dump).unwrap();
+        assert_eq!(dump, serde_json::from_str(&json).unwrap());
+    }
+    unsafe { pool.deallocate(blocks[1], layout) };
+}
+
+#[test]
+fn dump_structure_flags_cycles() {
+    use std::alloc::Allocator;
+
+    let layout = Layout::new::<u64>();
+    let pool = MemoryPool::new(4, layout);
+    let first = pool.allocate(layout).unwrap().as_non_null_ptr();
+    let second = pool.allocate(layout).unwrap().as_non_null_ptr();
+    unsafe {
+        pool.deallocate(first, layout);
+        pool.deallocate(second, layout);
+
+        // Corrupt the link of the first block to point back at the second.
+        first.cast::<Option<NonNull<u8>>>().write(Some(second));
+    }
+
+    let dump = pool.dump_structure();
+    assert!(dump.cyclic);
+    assert_eq!(vec![second.as_ptr() as usize, first.as_ptr() as usize], dump.free_nodes);
+    assert_eq!(None, dump.tail);
+    assert!(!pool.is_poisoned());
+
+    // A link out of the region is flagged as well.
+    let mut outside = 0u64;
+    unsafe { first.cast::<Option<NonNull<u8>>>().write(Some(NonNull::from(&mut outside).cast())) };
+    let dump = pool.dump_structure();
+    assert!(!dump.cyclic);
+    assert_eq!(Some(&mut outside as *mut u64 as usize), dump.invalid_link);
+}
+
+#[test]
+#[should_panic(expected = "bump only pools cannot tell freed blocks apart")]
+fn reset_with_bump_only() {
+    let mut pool = MemoryPool::builder(4, Layout::new::<u32>()).bump_only(true).build();
+    drop(Box::new_in(1u32, &pool));
+    pool.reset_with(|_| {});
+}
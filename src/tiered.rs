@@ -0,0 +1,75 @@
+use core::ptr::NonNull;
+
+use alloc::alloc::{Allocator, AllocError, Layout};
+use alloc::vec::Vec;
+
+use crate::{MemoryPool, PoolIntrospect, PtrStatus};
+
+/// A chain of memory pools with increasing block sizes. Each allocation is
+/// served by the smallest tier whose blocks fit it, escalating to larger tiers
+/// when a tier is exhausted.
+pub struct TieredPool {
+    /// The tiers, ordered by increasing block size.
+    tiers: Vec<MemoryPool>,
+}
+
+impl TieredPool {
+    /// Create a tiered pool from the given pools. They are ordered by block
+    /// size, so they can be passed in any order.
+    pub fn new(tiers: impl IntoIterator<Item = MemoryPool>) -> Self {
+        let mut tiers: Vec<_> = tiers.into_iter().collect();
+        tiers.sort_by_key(MemoryPool::block_size);
+        Self { tiers }
+    }
+
+    /// The tiers, ordered by increasing block size.
+    pub fn tiers(&self) -> &[MemoryPool] {
+        &self.tiers
+    }
+
+    /// The tier that owns the given pointer, if any.
+    fn tier_of(&self, ptr: NonNull<u8>) -> Option<&MemoryPool> {
+        self.tiers.iter().find(|tier| tier.validate_ptr(ptr) != PtrStatus::Foreign)
+    }
+}
+
+/// The capacity, length and availability are summed over all tiers. As the
+/// tiers differ in block size, the block size is that of the largest tier, or
+/// zero without any tiers.
+impl PoolIntrospect for TieredPool {
+    fn capacity(&self) -> usize {
+        self.tiers.iter().map(MemoryPool::capacity).sum()
+    }
+
+    fn len(&self) -> usize {
+        self.tiers.iter().map(MemoryPool::len).sum()
+    }
+
+    fn block_size(&self) -> usize {
+        self.tiers.last().map_or(0, MemoryPool::block_size)
+    }
+
+    fn available(&self) -> usize {
+        self.tiers.iter().map(MemoryPool::available).sum()
+    }
+}
+
+unsafe impl Allocator for TieredPool {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        // Tiers that are too small or exhausted fail, so we move on to the
+        // next larger one.
+        self.tiers.iter()
+            .find_map(|tier| tier.allocate(layout).ok())
+            .ok_or(AllocError)
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        let tier = self.tier_of(ptr);
+        // Check if the given pointer is contained in the allocator.
+        debug_assert!(tier.is_some());
+
+        if let Some(tier) = tier {
+            tier.deallocate(ptr, layout);
+        }
+    }
+}